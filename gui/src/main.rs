@@ -1,10 +1,22 @@
 #![windows_subsystem = "windows"]
 
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use i_slint_backend_winit::{winit, WinitWindowAccessor, WinitWindowEventResult};
 use rfd;
-use slint::{self, ComponentHandle};
+use slint::{self, ComponentHandle, ModelRc, StandardListViewItem, VecModel};
 use tokio;
 
-use nekotatsu::command::{self, CommandResult, Commands};
+use nekotatsu::command::{self, CommandResult, Commands, DownloadProgress};
+use nekotatsu::nekotatsu_core::config::{ConfigFile, SourceFilterEntry};
 
 mod application {
     slint::include_modules!();
@@ -18,10 +30,113 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// GUI settings kept between sessions, stored in the data directory as `key=value` lines
+#[derive(Debug, Default)]
+struct Settings {
+    whitelist: String,
+    blacklist: String,
+}
+
+impl Settings {
+    fn path() -> std::path::PathBuf {
+        command::data_dir().join("gui_settings.txt")
+    }
+
+    /// Missing or unreadable settings fall back to the defaults
+    fn load() -> Self {
+        let mut settings = Self::default();
+        let contents = std::fs::read_to_string(Self::path()).unwrap_or_default();
+        for (key, value) in contents.lines().filter_map(|line| line.split_once('=')) {
+            match key {
+                "whitelist" => settings.whitelist = value.to_string(),
+                "blacklist" => settings.blacklist = value.to_string(),
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(command::data_dir())?;
+        std::fs::write(
+            Self::path(),
+            format!(
+                "whitelist={}\nblacklist={}\n",
+                self.whitelist, self.blacklist
+            ),
+        )
+    }
+}
+
+/// Parses a comma separated list of source names, URLs or IDs;
+/// returns `None` if there are no entries
+fn parse_filter_list(list: &str) -> Option<Vec<SourceFilterEntry>> {
+    let entries = list
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let Ok(entry) = entry.parse::<SourceFilterEntry>();
+            entry
+        })
+        .collect::<Vec<_>>();
+    (!entries.is_empty()).then_some(entries)
+}
+
+/// Names the filter entries that match no known source, since they silently filter nothing
+fn filter_warning(
+    whitelist: &Option<Vec<SourceFilterEntry>>,
+    blacklist: &Option<Vec<SourceFilterEntry>>,
+) -> String {
+    let entries = whitelist
+        .iter()
+        .chain(blacklist.iter())
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return String::new();
+    }
+    match command::unmatched_filter_entries(&entries) {
+        Ok(unmatched) if unmatched.is_empty() => String::new(),
+        Ok(unmatched) => format!(
+            "No source matches {}; check the spelling or use the source ID",
+            unmatched
+                .iter()
+                .map(|entry| match entry {
+                    SourceFilterEntry::Id(id) => format!("'{id}'"),
+                    SourceFilterEntry::Name(text) | SourceFilterEntry::Url(text) => {
+                        format!("'{text}'")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(_) => String::from(
+            "Whitelist and blacklist can't be checked until the data files are updated",
+        ),
+    }
+}
+
+/// One line per data file, i.e. whether it is missing and when it was last updated
+fn data_status_text() -> String {
+    command::data_file_status()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn run_app_inner() -> Result<(), slint::PlatformError> {
     let app = application::Application::new()?;
+    app.set_data_status(data_status_text().into());
+    let settings = Settings::load();
+    app.set_whitelist(settings.whitelist.into());
+    app.set_blacklist(settings.blacklist.into());
+    let cancel_flag: Rc<RefCell<Option<Arc<AtomicBool>>>> = Rc::new(RefCell::new(None));
 
     let cc_handle = app.as_weak();
+    let cc_cancel_flag = cancel_flag.clone();
     app.on_convert_clicked(move || {
         let app = cc_handle.unwrap();
         let input = app.get_in_path().to_string();
@@ -29,30 +144,113 @@ fn run_app_inner() -> Result<(), slint::PlatformError> {
         let favorites_name = app.get_library_name().to_string();
         let verbose = app.get_verbose_output();
         let print_output = !app.get_view_output();
+        let whitelist = parse_filter_list(&app.get_whitelist());
+        let blacklist = parse_filter_list(&app.get_blacklist());
+        app.set_filter_warning(filter_warning(&whitelist, &blacklist).into());
+        let config = ConfigFile {
+            whitelist,
+            blacklist,
+            overrides: None,
+            builtin_sources: None,
+            thumbnails: None,
+            url_bases: None,
+            categories: None,
+        };
         let cc_handle = app.as_weak();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        cc_cancel_flag.replace(Some(cancelled.clone()));
         app.set_processing(true);
+        app.set_converting(true);
         tokio::spawn(async move {
             let result = command::run_command(Commands::Convert {
                 input,
+                more_inputs: Vec::new(),
                 output,
+                output_dir: None,
                 favorites_name,
                 verbose,
                 very_verbose: false,
+                quiet: false,
                 reverse: false,
-                soft_match: false,
+                input_format: None,
+                output_format: None,
+                compression: None,
+                soft_match: None,
+                lang: None,
                 force: true,
+                overwrite_policy: None,
                 print_output,
                 config_file: None,
+                parsers: None,
+                sources: None,
+                whitelist: Vec::new(),
+                blacklist: Vec::new(),
+                config: Some(config),
+                cancel_flag: Some(cancelled.clone()),
+                since: None,
+                category: Vec::new(),
+                exclude_category: Vec::new(),
+                rename_category: Vec::new(),
+                chapter_url_base: Vec::new(),
+                favorites_category: Vec::new(),
+                flatten_categories: false,
+                strip_read: false,
+                tags_from: None,
+                max_bookmarks: None,
+                fail_fast: false,
+                keep_going: true,
+                interactive: false,
+                include_unmatched: false,
+                keep_local: false,
+                unknown_state: None,
+                list_converted: false,
+                unmatched_out: None,
+                report_missing_parsers: None,
+                issue_template: None,
+                merge_into: None,
+                target_version: None,
             });
             cc_handle
                 .upgrade_in_event_loop(move |app| {
                     app.set_processing(false);
+                    app.set_converting(false);
                     match application::ChildWindow::new() {
                         Ok(child) => {
+                            let output_folder = match &result {
+                                Ok(
+                                    CommandResult::Success(path, _)
+                                    | CommandResult::Incomplete { path, .. },
+                                ) => std::path::Path::new(path).parent().map(|dir| {
+                                    // A bare file name is relative to the working directory
+                                    if dir.as_os_str().is_empty() {
+                                        std::path::PathBuf::from(".")
+                                    } else {
+                                        dir.to_path_buf()
+                                    }
+                                }),
+                                _ => None,
+                            };
+                            child.set_has_output(output_folder.is_some());
                             match result {
                                 Ok(result) => {
-                                    if let crate::CommandResult::Success(path, output) = result {
-                                        child.set_description(format!("Saved to '{path}'").into());
+                                    let saved = match result {
+                                        crate::CommandResult::Success(path, output) => {
+                                            Some((format!("Saved to '{path}'"), output))
+                                        }
+                                        crate::CommandResult::Incomplete {
+                                            path,
+                                            output,
+                                            errored,
+                                        } => Some((
+                                            format!(
+                                                "Saved to '{path}' ({errored} manga failed to convert)"
+                                            ),
+                                            output,
+                                        )),
+                                        crate::CommandResult::None => None,
+                                    };
+                                    if let Some((description, output)) = saved {
+                                        child.set_description(description.into());
                                         if !print_output {
                                             child.set_lines(output.lines().count() as i32);
                                             child.set_child_text(output.into());
@@ -61,17 +259,28 @@ fn run_app_inner() -> Result<(), slint::PlatformError> {
                                         }
                                     }
                                 }
+                                Err(_) if cancelled.load(Ordering::Relaxed) => {
+                                    child.set_description("Conversion was cancelled".into());
+                                }
                                 Err(e) => {
                                     child.set_description(
                                         format!("Stopped with error '{}'", e.to_string()).into(),
                                     );
                                 }
                             };
+                            child.invoke_apply_theme(app.get_theme());
                             let cc_handle = child.as_weak();
                             child.on_close_clicked(move || {
                                 let child = cc_handle.unwrap();
                                 child.hide().unwrap();
                             });
+                            child.on_open_folder_clicked(move || {
+                                if let Some(folder) = &output_folder {
+                                    if let Err(e) = open::that_detached(folder) {
+                                        println!("Error: {e}");
+                                    }
+                                }
+                            });
                             child.window().set_position(app.window().position());
                             child.show().unwrap();
                         }
@@ -84,6 +293,95 @@ fn run_app_inner() -> Result<(), slint::PlatformError> {
         });
     });
 
+    app.on_cancel_clicked(move || {
+        if let Some(flag) = cancel_flag.borrow().as_ref() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+
+    let pc_handle = app.as_weak();
+    app.on_preview_clicked(move || {
+        let app = pc_handle.unwrap();
+        let input = app.get_in_path().to_string();
+        let soft_match = app.get_soft_match();
+        let pc_handle = app.as_weak();
+        app.set_processing(true);
+        tokio::task::spawn_blocking(move || {
+            let result = command::list_sources(&input, soft_match);
+            pc_handle
+                .upgrade_in_event_loop(move |app| {
+                    app.set_processing(false);
+                    match application::PreviewWindow::new() {
+                        Ok(preview) => {
+                            match result {
+                                Ok(matches) => {
+                                    let matched =
+                                        matches.iter().filter(|s| s.parser.is_some()).count();
+                                    preview.set_description(
+                                        format!(
+                                            "{matched} of {} sources matched a Kotatsu parser",
+                                            matches.len()
+                                        )
+                                        .into(),
+                                    );
+                                    let rows = matches
+                                        .into_iter()
+                                        .map(|source| {
+                                            let row: Vec<StandardListViewItem> = vec![
+                                                source.name.as_str().into(),
+                                                source.id.to_string().as_str().into(),
+                                                source.parser.as_deref().unwrap_or("none").into(),
+                                                source.manga_count.to_string().as_str().into(),
+                                            ];
+                                            ModelRc::new(VecModel::from(row))
+                                        })
+                                        .collect::<Vec<_>>();
+                                    preview.set_rows(ModelRc::new(VecModel::from(rows)));
+                                }
+                                Err(e) => {
+                                    preview.set_description(
+                                        format!("Stopped with error '{e}'").into(),
+                                    );
+                                }
+                            }
+                            preview.invoke_apply_theme(app.get_theme());
+                            let pc_handle = preview.as_weak();
+                            preview.on_close_clicked(move || {
+                                let preview = pc_handle.unwrap();
+                                preview.hide().unwrap();
+                            });
+                            preview.window().set_position(app.window().position());
+                            preview.show().unwrap();
+                        }
+                        Err(e) => {
+                            println!("Error: {e}");
+                        }
+                    }
+                })
+                .unwrap();
+        });
+    });
+
+    // Slint doesn't expose file drops itself, so listen to the winit events directly;
+    // this is a no-op on other backends
+    let dd_handle = app.as_weak();
+    app.window().on_winit_window_event(move |_, event| {
+        if let winit::event::WindowEvent::DroppedFile(path) = event {
+            let is_backup = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    [".tachibk", ".proto.gz", ".zip"]
+                        .iter()
+                        .any(|ext| name.ends_with(ext))
+                });
+            if let Some(app) = dd_handle.upgrade().filter(|_| is_backup) {
+                app.set_in_path(path.display().to_string().into());
+            }
+        }
+        WinitWindowEventResult::Propagate
+    });
+
     let ic_handle = app.as_weak();
     app.on_input_clicked(move || {
         let app = ic_handle.unwrap();
@@ -124,10 +422,37 @@ fn run_app_inner() -> Result<(), slint::PlatformError> {
     });
 
     let uc_handle = app.as_weak();
+    let progress_timer = Rc::new(slint::Timer::default());
     app.on_update_clicked(move || {
         let app = uc_handle.unwrap();
         let uc_handle = app.as_weak();
+        let progress = Arc::new(DownloadProgress::default());
         app.set_processing(true);
+        app.set_updating(true);
+        app.set_download_progress(0.0);
+
+        let timer_handle = app.as_weak();
+        let timer_progress = progress.clone();
+        let timer = progress_timer.clone();
+        progress_timer.start(
+            slint::TimerMode::Repeated,
+            Duration::from_millis(100),
+            move || {
+                let Some(app) = timer_handle.upgrade() else {
+                    return;
+                };
+                if !app.get_updating() {
+                    timer.stop();
+                    return;
+                }
+                let total = timer_progress.total.load(Ordering::Relaxed);
+                if total > 0 {
+                    let downloaded = timer_progress.downloaded.load(Ordering::Relaxed);
+                    app.set_download_progress(downloaded as f32 / total as f32);
+                }
+            },
+        );
+
         tokio::task::spawn_blocking(move || {
             let _ = command::run_command(Commands::Update {
                 kotatsu_link: String::from(
@@ -137,13 +462,27 @@ fn run_app_inner() -> Result<(), slint::PlatformError> {
                     "https://raw.githubusercontent.com/keiyoushi/extensions/repo/index.min.json",
                 ),
                 force_download: false,
+                parser_stats: false,
+                proxy: None,
+                progress: Some(progress),
             });
             uc_handle
-                .upgrade_in_event_loop(|app| app.set_processing(false))
+                .upgrade_in_event_loop(|app| {
+                    app.set_processing(false);
+                    app.set_updating(false);
+                    app.set_data_status(data_status_text().into());
+                })
                 .unwrap();
         });
     });
 
     app.run()?;
+    let settings = Settings {
+        whitelist: app.get_whitelist().to_string(),
+        blacklist: app.get_blacklist().to_string(),
+    };
+    if let Err(e) = settings.save() {
+        println!("Error: {e}");
+    }
     Ok(())
 }
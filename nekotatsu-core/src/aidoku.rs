@@ -0,0 +1,320 @@
+use serde::Deserialize;
+use std::{collections::HashMap, io::Read};
+
+use crate::{extensions::ExtensionList, nekotatsu::neko, source_ids::MANGADEX};
+
+/// Seconds between the unix epoch and Swift's reference date (2001-01-01)
+const SWIFT_REFERENCE_DATE: f64 = 978_307_200.0;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AidokuBackup {
+    #[serde(default)]
+    pub library: Vec<AidokuLibraryManga>,
+    #[serde(default)]
+    pub history: Vec<AidokuHistory>,
+    #[serde(default)]
+    pub manga: Vec<AidokuManga>,
+    #[serde(default)]
+    pub chapters: Vec<AidokuChapter>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AidokuLibraryManga {
+    pub manga_id: String,
+    pub source_id: String,
+    #[serde(default)]
+    pub date_added: f64,
+    #[serde(default)]
+    pub last_updated: f64,
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AidokuHistory {
+    pub source_id: String,
+    pub manga_id: String,
+    pub chapter_id: String,
+    #[serde(default)]
+    pub date_read: f64,
+    pub progress: Option<i32>,
+    pub total: Option<i32>,
+    #[serde(default)]
+    pub completed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AidokuManga {
+    pub id: String,
+    pub source_id: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub artist: Option<String>,
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub cover: Option<String>,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub status: i32,
+    #[serde(default)]
+    pub viewer: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AidokuChapter {
+    pub source_id: String,
+    pub manga_id: String,
+    pub id: String,
+    pub title: Option<String>,
+    pub scanlator: Option<String>,
+    pub chapter: Option<f32>,
+    pub date_uploaded: Option<f64>,
+    #[serde(default)]
+    pub source_order: i32,
+}
+
+/// Reads a JSON Aidoku backup; binary property list backups are not supported
+pub fn decode_aidoku_backup<R: Read>(mut reader: R) -> std::io::Result<AidokuBackup> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Converts a Swift date (seconds since 2001-01-01) to unix milliseconds
+fn swift_date_to_millis(date: f64) -> i64 {
+    if date == 0.0 {
+        0
+    } else {
+        ((date + SWIFT_REFERENCE_DATE) * 1000.0) as i64
+    }
+}
+
+/// Normalizes a source name for comparison, e.g. "Asura Scans" and "asurascans"
+fn compact_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Finds the Tachiyomi source matching an Aidoku source id such as `en.asurascans`
+pub fn resolve_source_id(aidoku_id: &str, extensions: &ExtensionList) -> Option<i64> {
+    let (lang, name) = aidoku_id.split_once('.').unwrap_or(("", aidoku_id));
+    let name = compact_name(name);
+    if name == "mangadex" {
+        return Some(MANGADEX);
+    }
+
+    let lang_matches = |source_lang: &str| match lang {
+        "multi" | "all" | "" => true,
+        lang => source_lang == lang || source_lang == "all",
+    };
+    extensions
+        .sources()
+        .filter(|source| compact_name(&source.name) == name)
+        .find(|source| lang_matches(&source.lang))
+        .and_then(|source| source.id.parse().ok())
+}
+
+/// Tachiyomi stores paths relative to the source's domain
+fn relative_path(url: &str) -> String {
+    match url.split_once("://") {
+        Some((_, rest)) => rest
+            .find('/')
+            .map_or_else(|| String::from("/"), |i| rest[i..].to_string()),
+        None => url.to_string(),
+    }
+}
+
+impl AidokuBackup {
+    /// Converts into the neko backup shape used by the rest of the conversion;
+    /// also returns the Aidoku source ids that could not be matched to a Tachiyomi source,
+    /// manga from those sources are left out
+    pub fn to_neko_backup(&self, extensions: &ExtensionList) -> (neko::Backup, Vec<String>) {
+        let mut resolved: HashMap<&str, Option<i64>> = HashMap::new();
+        let mut unresolved = Vec::new();
+        for source_id in self.manga.iter().map(|m| m.source_id.as_str()) {
+            resolved.entry(source_id).or_insert_with(|| {
+                let id = resolve_source_id(source_id, extensions);
+                if id.is_none() {
+                    unresolved.push(source_id.to_string());
+                }
+                id
+            });
+        }
+
+        let category_index: HashMap<&str, i32> = self
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i as i32))
+            .collect();
+        let library: HashMap<(&str, &str), &AidokuLibraryManga> = self
+            .library
+            .iter()
+            .map(|l| ((l.source_id.as_str(), l.manga_id.as_str()), l))
+            .collect();
+        let history: HashMap<(&str, &str, &str), &AidokuHistory> = self
+            .history
+            .iter()
+            .map(|h| {
+                let key = (
+                    h.source_id.as_str(),
+                    h.manga_id.as_str(),
+                    h.chapter_id.as_str(),
+                );
+                (key, h)
+            })
+            .collect();
+
+        let backup_manga = self
+            .manga
+            .iter()
+            .filter_map(|manga| {
+                let source = resolved.get(manga.source_id.as_str()).copied().flatten()?;
+                let is_mangadex = source == MANGADEX;
+                let key = (manga.source_id.as_str(), manga.id.as_str());
+                let entry = library.get(&key);
+
+                let mut chapters = Vec::new();
+                let mut manga_history = Vec::new();
+                for chapter in self
+                    .chapters
+                    .iter()
+                    .filter(|c| c.source_id == manga.source_id && c.manga_id == manga.id)
+                {
+                    let url = if is_mangadex {
+                        format!("/chapter/{}", chapter.id)
+                    } else {
+                        chapter.id.clone()
+                    };
+                    let read = history.get(&(key.0, key.1, chapter.id.as_str()));
+                    if let Some(read) = read {
+                        manga_history.push(neko::BackupHistory {
+                            url: url.clone(),
+                            last_read: swift_date_to_millis(read.date_read),
+                            read_duration: 0,
+                        });
+                    }
+                    let progress = read.and_then(|r| r.progress).unwrap_or(0);
+                    chapters.push(neko::BackupChapter {
+                        url,
+                        name: chapter.title.clone().unwrap_or_default(),
+                        scanlator: chapter.scanlator.clone().unwrap_or_default(),
+                        read: read.is_some_and(|r| r.completed),
+                        last_page_read: progress,
+                        date_upload: chapter.date_uploaded.map_or(0, swift_date_to_millis),
+                        chapter_number: chapter.chapter.unwrap_or(-1.0),
+                        source_order: chapter.source_order,
+                        pages_left: read
+                            .and_then(|r| r.total)
+                            .map_or(0, |total| (total - progress).max(0)),
+                        ..Default::default()
+                    });
+                }
+
+                let url = if is_mangadex {
+                    format!("/manga/{}", manga.id)
+                } else {
+                    manga
+                        .url
+                        .as_deref()
+                        .map_or_else(|| manga.id.clone(), relative_path)
+                };
+                Some(neko::BackupManga {
+                    source,
+                    url,
+                    title: manga.title.clone(),
+                    artist: manga.artist.clone().unwrap_or_default(),
+                    author: manga.author.clone().unwrap_or_default(),
+                    description: manga.desc.clone().unwrap_or_default(),
+                    genre: manga.tags.clone(),
+                    // Aidoku has no "licensed" or "publishing finished" statuses
+                    status: match manga.status {
+                        3 => 5,
+                        4 => 6,
+                        status => status,
+                    },
+                    thumbnail_url: manga.cover.clone().unwrap_or_default(),
+                    date_added: entry.map_or(0, |e| swift_date_to_millis(e.date_added)),
+                    last_update: entry.map_or(0, |e| swift_date_to_millis(e.last_updated)),
+                    // Aidoku orders right-to-left before left-to-right, Tachiyomi the other way around
+                    viewer_flags: match manga.viewer {
+                        1 => 2,
+                        2 => 1,
+                        viewer => viewer,
+                    },
+                    chapters,
+                    categories: entry.map_or_else(Vec::new, |e| {
+                        e.categories
+                            .iter()
+                            .filter_map(|name| category_index.get(name.as_str()).copied())
+                            .collect()
+                    }),
+                    favorite: entry.is_some(),
+                    history: manga_history,
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let backup_categories = self
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(order, name)| neko::BackupCategory {
+                name: name.clone(),
+                order: order as i32,
+                ..Default::default()
+            })
+            .collect();
+
+        (
+            neko::Backup {
+                backup_manga,
+                backup_categories,
+            },
+            unresolved,
+        )
+    }
+}
+
+#[test]
+fn aidoku_backup_to_neko() {
+    let json = r#"{
+        "library": [{"mangaId": "abcd", "sourceId": "multi.mangadex", "dateAdded": 700000000, "categories": ["Reading"]}],
+        "history": [{"sourceId": "multi.mangadex", "mangaId": "abcd", "chapterId": "c1", "dateRead": 700000000, "progress": 3, "total": 10, "completed": false}],
+        "manga": [
+            {"id": "abcd", "sourceId": "multi.mangadex", "title": "Dex Manga", "status": 3, "viewer": 1},
+            {"id": "efgh", "sourceId": "en.nowhere", "title": "Lost Manga"}
+        ],
+        "chapters": [{"sourceId": "multi.mangadex", "mangaId": "abcd", "id": "c1", "chapter": 1.0, "sourceOrder": 0}],
+        "categories": ["Reading"],
+        "date": 700000000
+    }"#;
+    let backup = decode_aidoku_backup(json.as_bytes()).unwrap();
+    let (neko, unresolved) = backup.to_neko_backup(&ExtensionList::default());
+
+    assert_eq!(unresolved, vec!["en.nowhere".to_string()]);
+    assert_eq!(neko.backup_manga.len(), 1);
+    let manga = &neko.backup_manga[0];
+    assert_eq!(manga.url, "/manga/abcd");
+    assert_eq!(manga.source, MANGADEX);
+    assert_eq!((manga.status, manga.viewer_flags), (5, 2));
+    assert_eq!(manga.categories, vec![0]);
+    assert!(manga.favorite);
+    assert_eq!(manga.date_added, 1_678_307_200_000);
+    assert_eq!(manga.chapters[0].url, "/chapter/c1");
+    assert_eq!(manga.chapters[0].pages_left, 7);
+    assert_eq!(manga.history[0].last_read, 1_678_307_200_000);
+}
@@ -0,0 +1,443 @@
+use prost::Message;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    io::{self, Cursor, Read, Seek, Write},
+    path::Path,
+};
+
+use crate::{
+    kotatsu::{self, KotatsuIndexEntry, DEFAULT_APP_VERSION},
+    nekotatsu::neko,
+    CategoryMerge, Logger, MangaConversionResult,
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Kotatsu backup members that are combined when merging into an existing backup
+const MERGED_SECTIONS: [&str; 4] = ["history", "categories", "favourites", "bookmarks"];
+/// Kotatsu backup members whose entries embed a manga
+const MANGA_SECTIONS: [&str; 3] = ["history", "favourites", "bookmarks"];
+
+/// For input in the right format that fails to decode, as opposed to input in the wrong format
+fn corrupted(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("File appears truncated or corrupted, try downloading or copying it again. Original error: {e}"),
+    )
+}
+
+fn not_neko_backup(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Error occurred when parsing input archive, is it an actual neko backup? Original error: {e}"),
+    )
+}
+
+/// Decodes gzipped neko/Tachiyomi backups
+pub struct BackupReader;
+
+impl BackupReader {
+    pub fn read<R: Read>(mut reader: R) -> io::Result<neko::Backup> {
+        let mut compressed = Vec::new();
+        reader.read_to_end(&mut compressed)?;
+        if !compressed.starts_with(&GZIP_MAGIC) {
+            return Err(not_neko_backup("input is not gzip compressed"));
+        }
+
+        // The decoder checks the CRC and length stored at the end of the stream
+        let mut buf = Vec::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut buf)
+            .map_err(corrupted)?;
+
+        neko::Backup::decode(&mut buf.as_slice()).map_err(not_neko_backup)
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> io::Result<neko::Backup> {
+        Self::read(std::fs::File::open(path)?)
+    }
+
+    /// Opens a Kotatsu backup archive, checking every member against its CRC
+    pub fn open_kotatsu<R: Read + Seek>(reader: R) -> io::Result<zip::ZipArchive<R>> {
+        let mut archive = zip::ZipArchive::new(reader).map_err(|e| match e {
+            zip::result::ZipError::Io(e) => e,
+            zip::result::ZipError::InvalidArchive(_) => corrupted(e),
+            e => io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Error occurred when opening input archive, is it an actual Kotatsu backup? Original error: {e}"),
+            ),
+        })?;
+        for i in 0..archive.len() {
+            let mut member = archive.by_index(i).map_err(corrupted)?;
+            io::copy(&mut member, &mut io::sink()).map_err(corrupted)?;
+        }
+
+        Ok(archive)
+    }
+}
+
+/// Writes a converted backup as a Kotatsu backup archive, omitting empty sections
+pub struct BackupWriter<'a> {
+    result: &'a MangaConversionResult,
+    /// Members of an existing Kotatsu backup the result is merged into
+    existing: Vec<(String, Vec<u8>)>,
+    app_version: u64,
+}
+
+impl<'a> BackupWriter<'a> {
+    pub fn new(result: &'a MangaConversionResult) -> Self {
+        Self {
+            result,
+            existing: Vec::new(),
+            app_version: DEFAULT_APP_VERSION,
+        }
+    }
+
+    /// Version code of the Kotatsu release the backup targets, written to its index
+    pub fn with_app_version(self, app_version: u64) -> Self {
+        Self {
+            app_version,
+            ..self
+        }
+    }
+
+    /// Add the result to an existing Kotatsu backup instead of writing a fresh one.
+    /// Entries already in the backup are kept as they are, categories with the same
+    /// name are reused and new categories get IDs after the existing ones
+    pub fn merge_into<R: Read + Seek>(self, existing: R) -> io::Result<Self> {
+        let mut archive = BackupReader::open_kotatsu(existing)?;
+        let mut members = Vec::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            let mut member = archive.by_index(i).map_err(io::Error::from)?;
+            let mut contents = Vec::new();
+            member.read_to_end(&mut contents)?;
+            members.push((member.name().to_string(), contents));
+        }
+
+        Ok(Self {
+            existing: members,
+            ..self
+        })
+    }
+
+    fn existing_section(&self, name: &str) -> serde_json::Result<Vec<Value>> {
+        match self.existing.iter().find(|(member, _)| member == name) {
+            Some((_, contents)) => serde_json::from_slice(contents),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn merged_sections(&self) -> serde_json::Result<[(&'static str, Vec<Value>); 4]> {
+        let result = self.result;
+        let id_of = |value: &Value, key: &str| value.get(key).and_then(Value::as_i64);
+
+        let mut categories = self.existing_section("categories")?;
+        let mut favourites = self.existing_section("favourites")?;
+        let mut merge = CategoryMerge::new(
+            categories.iter().map(|c| {
+                (
+                    id_of(c, "category_id"),
+                    id_of(c, "sort_key").and_then(|key| i32::try_from(key).ok()),
+                    c.get("title").and_then(Value::as_str),
+                )
+            }),
+            favourites
+                .iter()
+                .filter_map(|f| Some((id_of(f, "manga_id")?, id_of(f, "category_id")?))),
+        );
+        for category in result.categories.iter() {
+            if let Some((id, sort_key)) = merge.add_category(category.category_id, &category.title)
+            {
+                let mut value = serde_json::to_value(category)?;
+                value["category_id"] = id.into();
+                value["sort_key"] = sort_key.into();
+                categories.push(value);
+            }
+        }
+        for favourite in result.favourites.iter() {
+            if let Some(category_id) =
+                merge.add_favourite(favourite.manga_id, favourite.category_id)
+            {
+                let mut value = serde_json::to_value(favourite)?;
+                value["category_id"] = category_id.into();
+                favourites.push(value);
+            }
+        }
+
+        let mut history = self.existing_section("history")?;
+        let mut read: HashSet<i64> = history
+            .iter()
+            .filter_map(|h| id_of(h, "manga_id"))
+            .collect();
+        for entry in result.history.iter() {
+            if read.insert(entry.manga_id) {
+                history.push(serde_json::to_value(entry)?);
+            }
+        }
+
+        let mut bookmarks = self.existing_section("bookmarks")?;
+        let mut bookmarked: HashSet<i64> = bookmarks
+            .iter()
+            .filter_map(|b| id_of(b.get("manga")?, "id"))
+            .collect();
+        for entry in result.bookmarks.iter() {
+            if bookmarked.insert(entry.manga.id) {
+                bookmarks.push(serde_json::to_value(entry)?);
+            }
+        }
+
+        Ok([
+            ("history", history),
+            ("categories", categories),
+            ("favourites", favourites),
+            ("bookmarks", bookmarks),
+        ])
+    }
+
+    pub fn write<W: Write + Seek>(&self, to_make: W, logger: &mut dyn Logger) -> io::Result<W> {
+        let result = self.result;
+        let options = zip::write::FileOptions::default();
+        let mut writer = zip::ZipWriter::new(to_make);
+        let sections = if self.existing.is_empty() {
+            vec![
+                ("history", serde_json::to_string_pretty(&result.history)),
+                (
+                    "categories",
+                    serde_json::to_string_pretty(&result.categories),
+                ),
+                (
+                    "favourites",
+                    serde_json::to_string_pretty(&result.favourites),
+                ),
+                ("bookmarks", serde_json::to_string_pretty(&result.bookmarks)),
+                (
+                    "index",
+                    serde_json::to_string_pretty(&[KotatsuIndexEntry::generate_for(
+                        self.app_version,
+                    )]),
+                ),
+            ]
+        } else {
+            let merged = self
+                .merged_sections()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let mut sections = merged
+                .iter()
+                .map(|(name, entries)| (*name, serde_json::to_string_pretty(entries)))
+                .collect::<Vec<_>>();
+            if !self.existing.iter().any(|(name, _)| name == "index") {
+                sections.push((
+                    "index",
+                    serde_json::to_string_pretty(&[KotatsuIndexEntry::generate_for(
+                        self.app_version,
+                    )]),
+                ));
+            }
+            sections
+        };
+        for (name, entry) in sections {
+            match entry {
+                Ok(json) if json.trim() != "[]" => {
+                    writer.start_file(name, options)?;
+                    writer.write_all(json.as_bytes())?;
+                }
+                Ok(_) => logger.log_info(&format!("{name} is empty, ommitted from converted backup")),
+                Err(e) => logger.log_warning(&format!(
+                    "Error occurred processing {name}, ommitted from converted backup, original error: {e}"
+                )),
+            }
+        }
+        // Everything else in an existing backup (settings, sources, ...) is kept untouched
+        for (name, contents) in self
+            .existing
+            .iter()
+            .filter(|(name, _)| !MERGED_SECTIONS.contains(&name.as_str()))
+        {
+            writer.start_file(name.as_str(), options)?;
+            writer.write_all(contents)?;
+        }
+
+        Ok(writer.finish()?)
+    }
+
+    pub fn to_bytes(&self, logger: &mut dyn Logger) -> io::Result<Vec<u8>> {
+        Ok(self.write(Cursor::new(Vec::new()), logger)?.into_inner())
+    }
+
+    /// Writes to a temporary file next to `path` first so an interrupted write
+    /// never leaves a truncated backup behind
+    pub fn write_file(&self, path: impl AsRef<Path>, logger: &mut dyn Logger) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".part");
+        let temp_path = path.with_file_name(temp_name);
+
+        let written = std::fs::File::create(&temp_path)
+            .and_then(|file| self.write(file, logger))
+            .and_then(|file| file.sync_all());
+        match written {
+            Ok(()) => std::fs::rename(&temp_path, path),
+            Err(e) => {
+                let _ = std::fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Writes a converted backup as a Kotatsu backup archive, discarding log messages;
+/// see [`BackupWriter`] for merging into existing backups and atomic file writes
+pub fn write_kotatsu_zip<W: Write + Seek>(
+    result: &MangaConversionResult,
+    to_make: W,
+) -> io::Result<W> {
+    BackupWriter::new(result).write(to_make, &mut Vec::new())
+}
+
+/// Re-applies [`kotatsu::correct_url`] and [`kotatsu::correct_identifier`] to a manga
+/// from a Kotatsu backup, returning its old and new ID if anything changed
+fn repair_manga(manga: &mut Value) -> Option<(i64, i64)> {
+    let source = manga.get("source")?.as_str()?.to_string();
+    let url = manga.get("url")?.as_str()?.to_string();
+    let old_id = manga.get("id")?.as_i64()?;
+    let corrected = kotatsu::correct_url(&source, &url);
+    let id = kotatsu::get_kotatsu_id(&source, &kotatsu::correct_identifier(&source, &corrected));
+    if corrected == url && id == old_id {
+        return None;
+    }
+
+    let public_url = manga
+        .get("public_url")
+        .and_then(Value::as_str)
+        .and_then(|public_url| public_url.strip_suffix(url.as_str()))
+        .map(|domain| format!("{domain}{corrected}"));
+    if let Some(public_url) = public_url {
+        manga["public_url"] = public_url.into();
+    }
+    manga["url"] = corrected.into();
+    manga["id"] = id.into();
+    Some((old_id, id))
+}
+
+/// Fixes the manga URLs and IDs in an existing Kotatsu backup, for backups converted
+/// before a correction for their source existed; everything else is copied as is.
+/// Returns the written archive and how many distinct manga were changed
+pub fn repair_kotatsu<R: Read + Seek, W: Write + Seek>(
+    existing: R,
+    to_make: W,
+) -> io::Result<(W, usize)> {
+    let mut archive = BackupReader::open_kotatsu(existing)?;
+    let options = zip::write::FileOptions::default();
+    let mut writer = zip::ZipWriter::new(to_make);
+    let mut repaired = HashSet::new();
+    for i in 0..archive.len() {
+        let mut member = archive.by_index(i).map_err(io::Error::from)?;
+        let name = member.name().to_string();
+        let mut contents = Vec::new();
+        member.read_to_end(&mut contents)?;
+
+        if MANGA_SECTIONS.contains(&name.as_str()) {
+            let mut entries: Vec<Value> = serde_json::from_slice(&contents).map_err(corrupted)?;
+            for entry in entries.iter_mut() {
+                let Some((old_id, id)) = entry.get_mut("manga").and_then(repair_manga) else {
+                    continue;
+                };
+                repaired.insert(old_id);
+                if entry.get("manga_id").is_some() {
+                    entry["manga_id"] = id.into();
+                }
+                // Bookmark entries each refer to their manga as well
+                if let Some(bookmarks) = entry.get_mut("bookmarks").and_then(Value::as_array_mut) {
+                    for bookmark in bookmarks.iter_mut() {
+                        if bookmark.get("manga_id").and_then(Value::as_i64) == Some(old_id) {
+                            bookmark["manga_id"] = id.into();
+                        }
+                    }
+                }
+            }
+            contents = serde_json::to_vec_pretty(&entries)?;
+        }
+
+        writer.start_file(name, options)?;
+        writer.write_all(&contents)?;
+    }
+
+    Ok((writer.finish()?, repaired.len()))
+}
+
+#[test]
+fn repair_corrects_urls_and_ids() {
+    let old_id = kotatsu::get_kotatsu_id("MANGADEX", "/manga/aaaa-bbbb");
+    let manga = serde_json::json!({
+        "id": old_id,
+        "url": "/manga/aaaa-bbbb",
+        "public_url": "https://mangadex.org/manga/aaaa-bbbb",
+        "source": "MANGADEX",
+    });
+    let mut zipped = Cursor::new(Vec::new());
+    let mut writer = zip::ZipWriter::new(&mut zipped);
+    for (name, contents) in [
+        (
+            "favourites",
+            serde_json::json!([{ "manga_id": old_id, "category_id": 1, "manga": manga }]),
+        ),
+        (
+            "bookmarks",
+            serde_json::json!([{ "manga": manga, "bookmarks": [{ "manga_id": old_id }] }]),
+        ),
+        ("settings", serde_json::json!({ "kept": true })),
+    ] {
+        writer
+            .start_file(name, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(contents.to_string().as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+    drop(writer);
+
+    let (repaired, count) =
+        repair_kotatsu(Cursor::new(zipped.into_inner()), Cursor::new(Vec::new())).unwrap();
+    assert_eq!(count, 1);
+    let mut archive = zip::ZipArchive::new(repaired).unwrap();
+    let mut section =
+        |name: &str| -> Value { serde_json::from_reader(archive.by_name(name).unwrap()).unwrap() };
+    let id = kotatsu::get_kotatsu_id("MANGADEX", "aaaa-bbbb");
+    let favourites = section("favourites");
+    assert_eq!(favourites[0]["manga_id"], id);
+    assert_eq!(favourites[0]["manga"]["url"], "/title/aaaa-bbbb");
+    assert_eq!(
+        favourites[0]["manga"]["public_url"],
+        "https://mangadex.org/title/aaaa-bbbb"
+    );
+    assert_eq!(section("bookmarks")[0]["bookmarks"][0]["manga_id"], id);
+    assert_eq!(section("settings")["kept"], true);
+}
+
+#[test]
+fn reader_tells_corruption_from_wrong_format() {
+    let mut gzipped = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    gzipped
+        .write_all(&neko::Backup::default().encode_to_vec())
+        .unwrap();
+    let gzipped = gzipped.finish().unwrap();
+    assert!(BackupReader::read(gzipped.as_slice()).is_ok());
+
+    let truncated = BackupReader::read(&gzipped[..gzipped.len() - 4]).unwrap_err();
+    assert_eq!(truncated.kind(), io::ErrorKind::InvalidData);
+    let not_gzip = BackupReader::read(b"PK\x03\x04".as_slice()).unwrap_err();
+    assert_eq!(not_gzip.kind(), io::ErrorKind::InvalidInput);
+
+    let mut zipped = Cursor::new(Vec::new());
+    let mut writer = zip::ZipWriter::new(&mut zipped);
+    writer
+        .start_file("history", zip::write::FileOptions::default())
+        .unwrap();
+    writer.write_all(b"[]").unwrap();
+    writer.finish().unwrap();
+    drop(writer);
+    let zipped = zipped.into_inner();
+    assert!(BackupReader::open_kotatsu(Cursor::new(zipped.as_slice())).is_ok());
+    let truncated =
+        BackupReader::open_kotatsu(Cursor::new(&zipped[..zipped.len() - 10])).unwrap_err();
+    assert_eq!(truncated.kind(), io::ErrorKind::InvalidData);
+}
@@ -1,13 +1,31 @@
-use crate::SourceInfo;
+use crate::{source_ids::BuiltinSources, SourceInfo, SourceKey};
+use regex::Regex;
 use serde::{de::Visitor, Deserialize};
+use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SourceFilterEntry {
     Id(i64),
     Name(String),
     Url(String),
 }
 
+impl std::str::FromStr for SourceFilterEntry {
+    type Err = std::convert::Infallible;
+
+    /// Parses an entry the same way as config files,
+    /// treating numbers as ids and anything containing a `.` as a url
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(id) = s.parse::<i64>() {
+            return Ok(SourceFilterEntry::Id(id));
+        }
+        if s.contains('.') {
+            return Ok(SourceFilterEntry::Url(s.to_ascii_lowercase()));
+        }
+        Ok(SourceFilterEntry::Name(s.to_lowercase()))
+    }
+}
+
 struct SourceFilterEntryVisitor;
 impl<'de> Visitor<'de> for SourceFilterEntryVisitor {
     type Value = SourceFilterEntry;
@@ -43,10 +61,56 @@ impl<'de> Deserialize<'de> for SourceFilterEntry {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ConfigFile {
     pub whitelist: Option<Vec<SourceFilterEntry>>,
     pub blacklist: Option<Vec<SourceFilterEntry>>,
+    /// Tachiyomi source ID or source name to Kotatsu parser name, used instead of automatic matching
+    pub overrides: Option<HashMap<String, String>>,
+    /// Source IDs of MangaDex and MANGA Plus if they differ from Tachiyomi's
+    pub builtin_sources: Option<BuiltinSources>,
+    /// Kotatsu parser name to how its cover URLs are turned into thumbnails
+    pub thumbnails: Option<HashMap<String, ThumbnailTransform>>,
+    /// Tachiyomi source ID or source name to the base URL the source moved to
+    pub url_bases: Option<HashMap<String, String>>,
+    /// Tachiyomi source ID or source name to a category its manga are added to
+    pub categories: Option<HashMap<String, String>>,
+}
+
+/// Turns a full-size cover URL into the thumbnail URL Kotatsu shows in lists
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ThumbnailTransform {
+    /// Appended to the cover URL, i.e. `.256.jpg` for MangaDex
+    Suffix { suffix: String },
+    /// Regex replacement on the cover URL; `$1` etc. refer to capture groups
+    Replace {
+        #[serde(deserialize_with = "deserialize_regex")]
+        pattern: Regex,
+        replacement: String,
+    },
+}
+
+impl ThumbnailTransform {
+    pub fn apply(&self, cover_url: &str) -> String {
+        match self {
+            ThumbnailTransform::Suffix { suffix } => format!("{cover_url}{suffix}"),
+            ThumbnailTransform::Replace {
+                pattern,
+                replacement,
+            } => pattern
+                .replace(cover_url, replacement.as_str())
+                .into_owned(),
+        }
+    }
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
 }
 
 impl Default for ConfigFile {
@@ -54,10 +118,39 @@ impl Default for ConfigFile {
         ConfigFile {
             whitelist: None,
             blacklist: None,
+            overrides: None,
+            builtin_sources: None,
+            thumbnails: None,
+            url_bases: None,
+            categories: None,
         }
     }
 }
 
+impl ConfigFile {
+    /// `overrides` in the form taken by [`crate::MangaConverter::with_overrides`]
+    pub fn source_overrides(&self) -> HashMap<SourceKey, String> {
+        source_keyed(&self.overrides)
+    }
+
+    /// `url_bases` in the form taken by [`crate::MangaConverter::with_url_bases`]
+    pub fn source_url_bases(&self) -> HashMap<SourceKey, String> {
+        source_keyed(&self.url_bases)
+    }
+
+    /// `categories` in the form taken by [`crate::MangaConverter::with_source_categories`]
+    pub fn source_categories(&self) -> HashMap<SourceKey, String> {
+        source_keyed(&self.categories)
+    }
+}
+
+fn source_keyed(map: &Option<HashMap<String, String>>) -> HashMap<SourceKey, String> {
+    map.iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((key.parse().ok()?, value.clone())))
+        .collect()
+}
+
 #[test]
 fn parse_config() -> Result<(), Box<dyn std::error::Error>> {
     let config = r#"
@@ -71,8 +164,25 @@ blacklist = [
     4201337,
     "mangasomething",
     "my.manga.me"
-]"#;
+]
+
+[overrides]
+2522335540328470744 = "COMICK_FUN"
+
+[thumbnails]
+MANGADEX = { suffix = ".512.jpg" }
+COMICK_FUN = { pattern = '\.(jpg|png)$', replacement = "-s.$1" }
+"#;
     let config: ConfigFile = toml::from_str(config)?;
+    let thumbnails = config.thumbnails.as_ref().expect("thumbnails are set");
+    assert_eq!(
+        thumbnails["MANGADEX"].apply("https://uploads.mangadex.org/covers/a/b.jpg"),
+        "https://uploads.mangadex.org/covers/a/b.jpg.512.jpg"
+    );
+    assert_eq!(
+        thumbnails["COMICK_FUN"].apply("https://meo.comick.pictures/cover.png"),
+        "https://meo.comick.pictures/cover-s.png"
+    );
 
     println!("{config:?}");
 
@@ -22,7 +22,7 @@ impl Default for SourceInfo {
 }
 
 #[allow(non_snake_case)]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct ExtensionInfo {
     pub name: String,
     pub pkg: String,
@@ -34,28 +34,59 @@ pub struct ExtensionInfo {
     pub sources: Vec<SourceInfo>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ExtensionList {
     inner: Vec<ExtensionInfo>,
+    /// Number of extension index entries that could not be read
+    skipped: usize,
 }
 
 impl Default for ExtensionList {
     fn default() -> Self {
-        Self { inner: Vec::new() }
+        Self {
+            inner: Vec::new(),
+            skipped: 0,
+        }
     }
 }
 
 impl ExtensionList {
     pub fn new(list: Vec<ExtensionInfo>) -> Self {
-        Self { inner: list }
+        Self {
+            inner: list,
+            skipped: 0,
+        }
+    }
+
+    pub fn try_from_file(file: std::fs::File) -> std::io::Result<Self> {
+        Self::try_from_reader(file)
     }
 
-    pub fn try_from_file(mut file: std::fs::File) -> std::io::Result<Self> {
+    /// Reads an extension index, skipping entries that don't fit [`ExtensionInfo`]
+    /// instead of rejecting the whole index; see [`Self::skipped`]
+    pub fn try_from_reader<R: Read>(mut reader: R) -> std::io::Result<Self> {
         let mut extensions = String::new();
-        file.read_to_string(&mut extensions)?;
-        Ok(Self {
-            inner: serde_json::from_str(&extensions)?,
-        })
+        reader.read_to_string(&mut extensions)?;
+        let (inner, skipped) = crate::parse_lenient(serde_json::from_str(&extensions)?);
+
+        Ok(Self { inner, skipped })
+    }
+
+    /// Number of extension index entries left out because they could not be read
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &SourceInfo> {
+        self.inner.iter().flat_map(|e| &e.sources)
+    }
+
+    /// The extension providing the source with this ID
+    pub fn get_extension(&self, id: i64) -> Option<&ExtensionInfo> {
+        let id = id.to_string();
+        self.inner
+            .iter()
+            .find(|e| e.sources.iter().any(|s| s.id == id))
     }
 
     pub fn get_source(&self, id: i64) -> Option<SourceInfo> {
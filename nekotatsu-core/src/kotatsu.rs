@@ -54,13 +54,23 @@ static DOMAIN_CAPTURE_METHODS: LazyLock<Vec<DomainCaptureMethod>> = LazyLock::ne
 ]
 });
 
+/// Value of [`KotatsuMangaBackup::rating`] Kotatsu treats as "unrated";
+/// used for every converted manga since neko backups do not store ratings
+pub const RATING_UNKNOWN: f32 = -1.0;
+
+/// Kotatsu's source for manga imported from local files
+pub const LOCAL_SOURCE: &str = "LOCAL";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KotatsuMangaBackup {
     pub id: i64,
     pub title: String,
-    pub alt_tile: Option<String>,
+    // Previously misspelled as `alt_tile`; still accepted when reading older conversions
+    #[serde(alias = "alt_tile")]
+    pub alt_title: Option<String>,
     pub url: String,
     pub public_url: String,
+    /// Between 0 and 1, or [`RATING_UNKNOWN`]
     pub rating: f32,
     pub nsfw: bool,
     pub cover_url: String,
@@ -68,9 +78,41 @@ pub struct KotatsuMangaBackup {
     pub state: String,
     pub author: String,
     pub source: String,
-    // neko backups do not provide the relevant links, only the names
-    // as such, this is just here to appease the expected json format
-    pub tags: [String; 0],
+    pub tags: Vec<KotatsuTagBackup>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KotatsuTagBackup {
+    pub id: i64,
+    pub title: String,
+    pub key: String,
+    pub source: String,
+    pub pinned: bool,
+}
+
+impl KotatsuTagBackup {
+    /// Neko backups only store genre names, not the parser's tag keys,
+    /// so the key is derived from the name; Kotatsu shows these tags
+    /// but searching by them may not work until the manga is refreshed
+    pub fn from_genre(source_name: &str, genre: &str) -> Self {
+        let key = genre.trim().to_lowercase().replace(' ', "-");
+        Self {
+            // Kotatsu's `TagEntity` id is the same hash over `{key}_{source}`
+            id: get_kotatsu_id("", &format!("{key}_{source_name}")),
+            title: genre.trim().to_string(),
+            key,
+            source: source_name.to_string(),
+            pinned: false,
+        }
+    }
+}
+
+#[test]
+fn tag_from_genre() {
+    let tag = KotatsuTagBackup::from_genre("MANGADEX", " Slice of Life ");
+    assert_eq!(tag.title, "Slice of Life");
+    assert_eq!(tag.key, "slice-of-life");
+    assert_eq!(tag.id, get_kotatsu_id("", "slice-of-life_MANGADEX"));
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -125,14 +167,14 @@ pub struct KotatsuBookmarkEntry {
     pub percent: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum KotatsuParserContentType {
     Manga,
     Hentai,
     Comics,
     Other,
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KotatsuParser {
     pub name: String,
     pub title: String,
@@ -141,6 +183,12 @@ pub struct KotatsuParser {
     pub domains: Vec<String>,
 }
 
+/// Reads a Kotatsu parser list, skipping entries that don't fit [`KotatsuParser`]
+/// instead of rejecting the whole list; also returns how many were skipped
+pub fn parse_parser_list(json: &str) -> serde_json::Result<(Vec<KotatsuParser>, usize)> {
+    Ok(crate::parse_lenient(serde_json::from_str(json)?))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct KotatsuIndexEntry {
     pub app_id: String,
@@ -148,11 +196,20 @@ pub struct KotatsuIndexEntry {
     pub created_at: u128,
 }
 
+/// `app_version` written to the backup index unless told otherwise; Kotatsu doesn't
+/// check it when restoring as of writing, so 0 works with every release
+pub const DEFAULT_APP_VERSION: u64 = 0;
+
 impl KotatsuIndexEntry {
     pub fn generate() -> Self {
+        Self::generate_for(DEFAULT_APP_VERSION)
+    }
+
+    /// Index for a backup targeting the Kotatsu version with this version code
+    pub fn generate_for(app_version: u64) -> Self {
         Self {
             app_id: String::from("com.github.phantomshift.nekotatsu"),
-            app_version: 0,
+            app_version,
             created_at: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
@@ -161,20 +218,50 @@ impl KotatsuIndexEntry {
     }
 }
 
+/// ID Kotatsu generates for manga and chapters (`MangaParser.generateUid`);
+/// a Java `String.hashCode`-style hash over the UTF-16 code units of the parser name
+/// followed by the URL, seeded with 1125899906842597 and wrapping on overflow.
+/// Favourites, history and bookmarks are only linked if this matches exactly.
 pub fn get_kotatsu_id(source_name: &str, url: &str) -> i64 {
     let mut id: i64 = 1125899906842597;
     source_name
-        .chars()
+        .encode_utf16()
         .for_each(|c| id = (31i64.overflowing_mul(id)).0.overflowing_add(c as i64).0);
-    url.chars()
+    url.encode_utf16()
         .for_each(|c| id = (31i64.overflowing_mul(id)).0.overflowing_add(c as i64).0);
     return id;
 }
 
+#[test]
+fn kotatsu_id_matches_kotlin() {
+    assert_eq!(get_kotatsu_id("", ""), 1125899906842597);
+    assert_eq!(
+        get_kotatsu_id("MANGADEX", "aaaa-bbbb"),
+        -2270108736253201371
+    );
+    assert_eq!(
+        get_kotatsu_id("MANGADEX", "f7888e6f-8a5c-4d1e-9d3b-7c6a5b4e3d2c"),
+        -5128008529635448512
+    );
+    assert_eq!(
+        get_kotatsu_id("COMICK_FUN", "/comic/foo"),
+        5524962811986516280
+    );
+    // Characters outside the BMP count as two UTF-16 code units, like in Kotlin
+    assert_eq!(get_kotatsu_id("MANGADEX", "🙂"), -3837963048103744915);
+}
+
 /// Correct identifiers for known sources; leaves alone if not implemented
 pub fn correct_identifier(source_name: &str, identifier: &str) -> String {
     match source_name {
         "MANGADEX" => identifier.replace("/title/", "").replace("/chapter/", ""),
+        // Kotatsu identifies MangaPlus titles and chapters by their numeric IDs,
+        // Tachiyomi stores them as `#/titles/{id}` and `#/viewer/{id}`, older versions without `#`
+        name if name.starts_with("MANGAPLUSPARSER") => identifier
+            .rsplit('/')
+            .next()
+            .unwrap_or(identifier)
+            .to_string(),
         _ => identifier.to_string(),
     }
 }
@@ -186,10 +273,57 @@ pub fn correct_url(source_name: &str, url: &str) -> String {
     }
 }
 
+/// Asura appends a hash to series slugs that Tachiyomi extensions have been known to drop
+static ASURA_SERIES_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^series/[\w-]+-[0-9a-f]{8}/?$").unwrap());
+
+/// Sanity checks a converted public url, including known url patterns for some sources;
+/// returns false if the url is likely to lead nowhere in Kotatsu
+pub fn validate_public_url(source_name: &str, public_url: &str) -> bool {
+    let path = public_url
+        .split_once("://")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .map_or("", |(_, path)| path);
+    if path.is_empty() {
+        return false;
+    }
+
+    match source_name {
+        "ASURASCANS" => ASURA_SERIES_PATH.is_match(path),
+        _ => true,
+    }
+}
+
+#[test]
+fn validate_asura_public_urls() {
+    assert!(validate_public_url(
+        "ASURASCANS",
+        "https://asuracomic.net/series/solo-leveling-f9659ca8"
+    ));
+    assert!(!validate_public_url(
+        "ASURASCANS",
+        "https://asuracomic.net/series/solo-leveling"
+    ));
+    assert!(!validate_public_url("MANGADEX", "https://mangadex.org"));
+    assert!(validate_public_url(
+        "MANGADEX",
+        "https://mangadex.org/title/aaaa-bbbb"
+    ));
+}
+
+/// Parser sources larger than this are skipped; the biggest ones upstream are a few dozen KiB
+const MAX_PARSER_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Contents and path of a parser source file
+type ParserSource = (String, String);
+
+/// Every parser source in the repo archive, and the paths of the files that could not be
+/// read along with the reason
 fn get_parser_definitions(
     archive: ZipArchive<Cursor<Vec<u8>>>,
-) -> std::io::Result<Vec<(String, String)>> {
+) -> std::io::Result<(Vec<ParserSource>, Vec<String>)> {
     let mut files = Vec::new();
+    let mut skipped = Vec::new();
 
     let root = archive
         .file_names()
@@ -208,17 +342,89 @@ fn get_parser_definitions(
         )) && path.ends_with(".kt")
         {
             let mut clone = archive.clone();
-            let mut file = clone.by_name(path)?;
-            let mut s = String::new();
-            file.read_to_string(&mut s)?;
-            files.push((s, path.to_string()));
+            let read = clone
+                .by_name(path)
+                .map_err(std::io::Error::from)
+                .and_then(|mut file| {
+                    if file.size() > MAX_PARSER_FILE_SIZE {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("file is larger than {MAX_PARSER_FILE_SIZE} bytes"),
+                        ));
+                    }
+                    let mut s = String::new();
+                    file.read_to_string(&mut s)?;
+                    Ok(s)
+                });
+            match read {
+                Ok(s) => files.push((s, path.to_string())),
+                Err(e) => skipped.push(format!("{path}: {e}")),
+            }
+        }
+    }
+
+    Ok((files, skipped))
+}
+
+/// Parser counts from [`update_parsers`], to tell whether the parser repo was read properly
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParserStats {
+    pub total: usize,
+    /// Parsers whose domains could not be found; these can only match by name
+    pub without_domains: usize,
+    pub manga: usize,
+    pub hentai: usize,
+    pub comics: usize,
+    pub other: usize,
+    /// Parser files that could not be read and why; their parsers are missing from the list
+    pub skipped_files: Vec<String>,
+}
+
+impl ParserStats {
+    pub fn from_parsers(parsers: &[KotatsuParser]) -> Self {
+        let mut stats = Self::default();
+        for parser in parsers {
+            stats.total += 1;
+            if parser.domains.is_empty() {
+                stats.without_domains += 1;
+            }
+            match parser.content_type {
+                KotatsuParserContentType::Manga => stats.manga += 1,
+                KotatsuParserContentType::Hentai => stats.hentai += 1,
+                KotatsuParserContentType::Comics => stats.comics += 1,
+                KotatsuParserContentType::Other => stats.other += 1,
+            }
         }
+        stats
+    }
+}
+
+#[test]
+fn parser_definitions_skip_unreadable_files() {
+    let site = "kotatsu-parsers-master/src/main/kotlin/org/koitharu/kotatsu/parsers/site";
+    let mut zipped = Cursor::new(Vec::new());
+    let mut writer = zip::ZipWriter::new(&mut zipped);
+    for (path, contents) in [
+        (format!("{site}/en/Good.kt"), b"class Good".as_slice()),
+        (format!("{site}/en/Bad.kt"), b"class \xff\xfe".as_slice()),
+    ] {
+        writer
+            .start_file(path, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(contents).unwrap();
     }
+    writer.finish().unwrap();
+    drop(writer);
 
-    Ok(files)
+    let archive = ZipArchive::new(Cursor::new(zipped.into_inner())).unwrap();
+    let (files, skipped) = get_parser_definitions(archive).unwrap();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].1.ends_with("Good.kt"));
+    assert_eq!(skipped.len(), 1);
+    assert!(skipped[0].contains("Bad.kt"));
 }
 
-pub fn update_parsers(new: &File, mut save_to: &File) -> std::io::Result<()> {
+pub fn update_parsers(new: &File, mut save_to: &File) -> std::io::Result<ParserStats> {
     let reader = BufReader::new(new);
     let bytes = Cursor::new(
         reader
@@ -226,7 +432,10 @@ pub fn update_parsers(new: &File, mut save_to: &File) -> std::io::Result<()> {
             .collect::<Result<Vec<u8>, std::io::Error>>()?,
     );
     let reader = zip::read::ZipArchive::new(bytes)?;
-    let files = get_parser_definitions(reader)?;
+    let (files, skipped_files) = get_parser_definitions(reader)?;
+    for skipped in skipped_files.iter() {
+        println!("[WARNING]: Kotatsu parser file skipped because it could not be read: {skipped}");
+    }
     let mut parsers = Vec::new();
     for (contents, path) in files.iter() {
         // (Known) parsers I will likely need to make custom code for: ExHentai and NineManga
@@ -266,5 +475,34 @@ pub fn update_parsers(new: &File, mut save_to: &File) -> std::io::Result<()> {
 
     save_to.write(&mut serde_json::to_vec(&parsers)?)?;
 
-    Ok(())
+    Ok(ParserStats {
+        skipped_files,
+        ..ParserStats::from_parsers(&parsers)
+    })
+}
+
+#[test]
+fn correct_mangaplus_identifiers() {
+    let manga = correct_url("MANGAPLUSPARSER_EN", "/titles/100020");
+    assert_eq!(manga, "/titles/100020");
+    assert_eq!(correct_identifier("MANGAPLUSPARSER_EN", &manga), "100020");
+    assert_eq!(
+        correct_identifier("MANGAPLUSPARSER_ES", "/viewer/1000486"),
+        "1000486"
+    );
+    assert_eq!(
+        correct_identifier("MANGAPLUSPARSER_EN", "#/titles/100020"),
+        "100020"
+    );
+    assert_eq!(
+        correct_identifier("MANGAPLUSPARSER_ES", "#/viewer/1000486"),
+        "1000486"
+    );
+    assert_eq!(
+        get_kotatsu_id(
+            "MANGAPLUSPARSER_EN",
+            &correct_identifier("MANGAPLUSPARSER_EN", &manga)
+        ),
+        get_kotatsu_id("MANGAPLUSPARSER_EN", "100020")
+    );
 }
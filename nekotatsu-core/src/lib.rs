@@ -1,14 +1,23 @@
 use extensions::SourceInfo;
-use prost::Message;
+use serde::Serialize;
 use std::{
     collections::{HashMap, HashSet},
     fmt::Debug,
     fs::File,
-    io::{self, Read, Write},
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
+pub mod aidoku;
+pub mod backup;
 pub mod config;
 pub mod extensions;
+pub mod source_ids;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 pub mod nekotatsu {
     pub mod neko {
         include!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/neko.backup.rs"));
@@ -29,21 +38,157 @@ pub trait Logger {
     fn log_very_verbose(&mut self, message: &str) -> () {
         self.log_verbose(message);
     }
+    /// Problems worth reporting even when other output is suppressed
+    fn log_warning(&mut self, message: &str) {
+        self.log_info(&format!("[WARNING] {message}"));
+    }
 
     fn capture_output(&mut self) -> String {
         String::new()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MangaConverter {
     sources: HashMap<i64, String>,
     parsers: Vec<KotatsuParser>,
     pub extensions: extensions::ExtensionList,
 
     soft_match: bool,
+    /// Names of sources that only matched a parser through soft match
+    soft_matched_sources: HashSet<String>,
+    /// Report soft matches as candidates instead of using them
+    soft_match_confirm: bool,
+    /// Source names and the parser soft match found for them, when not used
+    soft_match_candidates: HashMap<String, String>,
+    fail_fast: bool,
+    cancel_flag: Option<Arc<AtomicBool>>,
+    /// Parser locale to pick when several parsers match a source
+    preferred_locale: Option<String>,
+    webtoon_scroll: i32,
+    flatten_categories: bool,
+    include_unmatched: bool,
+    keep_local: bool,
+    /// Kotatsu state for manga whose status has no Kotatsu equivalent
+    unknown_state: String,
+    /// Names of categories whose manga are left out
+    excluded_categories: Vec<String>,
+    /// Old and new names of categories to rename
+    category_renames: Vec<(String, String)>,
+    convert_tags: bool,
+    /// Most bookmarks kept per manga; `None` keeps all of them
+    max_bookmarks: Option<usize>,
+    /// Parser list entries that could not be read
+    skipped_parsers: usize,
+    /// How each source in `sources` was matched
+    match_methods: HashMap<i64, MatchMethod>,
+    builtin_sources: source_ids::BuiltinSources,
+    /// Parser names picked explicitly for sources, checked before any matching
+    overrides: HashMap<SourceKey, String>,
+    /// Overrides left out because their parser does not exist
+    ignored_overrides: Vec<(SourceKey, String)>,
+    /// Base URLs that sources moved to, used instead of the extension's
+    url_bases: HashMap<SourceKey, String>,
+    /// Categories that all manga from a source are added to
+    source_categories: HashMap<SourceKey, String>,
+    /// Kotatsu parser name to how its thumbnails are derived from cover URLs
+    thumbnails: HashMap<String, config::ThumbnailTransform>,
+}
+
+/// Reason a single manga could not be converted
+#[derive(Debug, Clone)]
+pub enum ConversionError {
+    /// Manga from the local source (ID 0)
+    LocalManga { title: String },
+    /// Source ID is not present in the Tachiyomi extension list, as opposed to
+    /// [`ConversionError::ParserNotFound`] for known sources
+    UnknownSource { title: String, source_id: i64 },
+    /// Source is known but no matching Kotatsu parser was found
+    ParserNotFound {
+        title: String,
+        source_name: String,
+        base_url: String,
+        /// Names of the closest Kotatsu parsers
+        suggestions: Vec<String>,
+    },
+    /// Conversion was cancelled through the converter's cancel flag
+    Cancelled,
 }
 
+/// [`ConversionError`] of a single manga without its title, for reports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConversionErrorKind {
+    LocalManga,
+    UnknownSource {
+        source_id: i64,
+    },
+    ParserNotFound {
+        source_name: String,
+        base_url: String,
+        suggestions: Vec<String>,
+    },
+}
+
+impl ConversionError {
+    /// Title of the manga and why it failed; `None` for [`ConversionError::Cancelled`]
+    pub fn into_failure(self) -> Option<(String, ConversionErrorKind)> {
+        match self {
+            ConversionError::LocalManga { title } => Some((title, ConversionErrorKind::LocalManga)),
+            ConversionError::UnknownSource { title, source_id } => {
+                Some((title, ConversionErrorKind::UnknownSource { source_id }))
+            }
+            ConversionError::ParserNotFound {
+                title,
+                source_name,
+                base_url,
+                suggestions,
+            } => Some((
+                title,
+                ConversionErrorKind::ParserNotFound {
+                    source_name,
+                    base_url,
+                    suggestions,
+                },
+            )),
+            ConversionError::Cancelled => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::LocalManga { title } => write!(
+                f,
+                "Unable to convert '{title}', local manga currently unsupported"
+            ),
+            ConversionError::UnknownSource { title, source_id } => write!(
+                f,
+                "Unable to convert '{title}', source ID {source_id} is not in the extension index; if it is out of date, try `nekotatsu update --force-download`"
+            ),
+            ConversionError::ParserNotFound {
+                title,
+                source_name,
+                base_url,
+                suggestions,
+            } => {
+                write!(
+                    f,
+                    "Unable to convert '{title}' from source {source_name} ({base_url}), Kotatsu parser not found"
+                )?;
+                if !suggestions.is_empty() {
+                    write!(f, "; closest parsers: {}", suggestions.join(", "))?;
+                }
+                Ok(())
+            }
+            ConversionError::Cancelled => write!(f, "Conversion cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
 pub struct MangaConversionResult {
     pub categories: Vec<KotatsuCategoryBackup>,
     pub favourites: Vec<KotatsuFavouriteBackup>,
@@ -51,10 +196,258 @@ pub struct MangaConversionResult {
     pub bookmarks: Vec<KotatsuBookmarkBackup>,
     pub errored_sources: HashMap<String, String>,
     pub errored_sources_count: HashMap<String, usize>,
+    /// Closest Kotatsu parser names for each source in `errored_sources` that has no parser
+    pub parser_suggestions: HashMap<String, Vec<String>>,
     pub unknown_sources: HashSet<String>,
+    /// Sources that were matched to a parser only because of soft match
+    pub soft_matched_sources: Vec<String>,
+    /// Source names and the parser soft match would have used for them, when soft
+    /// matches have to be confirmed; their manga are not converted
+    pub soft_match_candidates: Vec<(String, String)>,
     pub total_manga: usize,
     pub errored_manga: usize,
+    /// Title and reason of every manga that failed to convert, in backup order
+    pub failures: Vec<(String, ConversionErrorKind)>,
     pub ignored_manga: usize,
+    /// Manga that were merged into an earlier entry with the same Kotatsu ID
+    pub merged_duplicates: usize,
+    /// Manga from sources without a Kotatsu parser that were converted anyway
+    pub included_unmatched: usize,
+    /// Manga left out because all of their categories were excluded
+    pub excluded_manga: usize,
+    /// Local manga kept as placeholders under Kotatsu's local source; their files
+    /// still have to be imported into Kotatsu
+    pub local_placeholders: usize,
+    /// Converted manga with tracker entries (MyAnimeList, AniList, ...), which aren't converted
+    pub tracked_manga: usize,
+}
+
+impl MangaConversionResult {
+    /// Adds the result of converting another backup. Categories with the same name are
+    /// combined and the others get IDs after the existing ones; manga already in this
+    /// result keep the more recent history entry and count as merged duplicates. Their
+    /// bookmarks are combined, keeping the `max_bookmarks` most recently read
+    pub fn merge(&mut self, other: MangaConversionResult, max_bookmarks: Option<usize>) {
+        let mut categories = CategoryMerge::new(
+            self.categories.iter().map(|c| {
+                (
+                    Some(c.category_id),
+                    Some(c.sort_key),
+                    Some(c.title.as_str()),
+                )
+            }),
+            self.favourites.iter().map(|f| (f.manga_id, f.category_id)),
+        );
+        for mut category in other.categories {
+            if let Some((id, sort_key)) =
+                categories.add_category(category.category_id, &category.title)
+            {
+                category.category_id = id;
+                category.sort_key = sort_key;
+                self.categories.push(category);
+            }
+        }
+        for mut favourite in other.favourites {
+            if let Some(category_id) =
+                categories.add_favourite(favourite.manga_id, favourite.category_id)
+            {
+                favourite.category_id = category_id;
+                self.favourites.push(favourite);
+            }
+        }
+
+        let history_index: HashMap<i64, usize> = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (h.manga_id, i))
+            .collect();
+        for entry in other.history {
+            match history_index.get(&entry.manga_id) {
+                Some(&i) => {
+                    self.merged_duplicates += 1;
+                    if entry.updated_at > self.history[i].updated_at {
+                        self.history[i] = entry;
+                    }
+                }
+                None => self.history.push(entry),
+            }
+        }
+
+        for entry in other.bookmarks {
+            match self
+                .bookmarks
+                .iter_mut()
+                .find(|b| b.manga.id == entry.manga.id)
+            {
+                Some(existing) => {
+                    let chapters: HashSet<i64> =
+                        existing.bookmarks.iter().map(|b| b.chapter_id).collect();
+                    existing.bookmarks.extend(
+                        entry
+                            .bookmarks
+                            .into_iter()
+                            .filter(|b| !chapters.contains(&b.chapter_id)),
+                    );
+                    if let Some(limit) = max_bookmarks {
+                        // `created_at` is the chapter's last read
+                        existing
+                            .bookmarks
+                            .sort_by_key(|b| std::cmp::Reverse(b.created_at));
+                        existing.bookmarks.truncate(limit);
+                    }
+                }
+                None => self.bookmarks.push(entry),
+            }
+        }
+
+        self.errored_sources.extend(other.errored_sources);
+        for (name, count) in other.errored_sources_count {
+            *self.errored_sources_count.entry(name).or_default() += count;
+        }
+        self.parser_suggestions.extend(other.parser_suggestions);
+        self.unknown_sources.extend(other.unknown_sources);
+        for source in other.soft_matched_sources {
+            if !self.soft_matched_sources.contains(&source) {
+                self.soft_matched_sources.push(source);
+            }
+        }
+        for candidate in other.soft_match_candidates {
+            if !self.soft_match_candidates.contains(&candidate) {
+                self.soft_match_candidates.push(candidate);
+            }
+        }
+        self.total_manga += other.total_manga;
+        self.errored_manga += other.errored_manga;
+        self.failures.extend(other.failures);
+        self.ignored_manga += other.ignored_manga;
+        self.merged_duplicates += other.merged_duplicates;
+        self.included_unmatched += other.included_unmatched;
+        self.excluded_manga += other.excluded_manga;
+        self.local_placeholders += other.local_placeholders;
+        self.tracked_manga += other.tracked_manga;
+    }
+}
+
+/// Reconciles categories and favourites added to the ones of a backup or result;
+/// shared by [`MangaConversionResult::merge`] and merging into an existing Kotatsu backup
+/// so both treat categories the same way
+struct CategoryMerge {
+    /// Category ID by ASCII lowercase title
+    titles: HashMap<String, i64>,
+    next_id: i64,
+    next_sort_key: i32,
+    /// Category ID of each added category in the merged result
+    ids: HashMap<i64, i64>,
+    favourited: HashSet<(i64, i64)>,
+}
+
+impl CategoryMerge {
+    /// Takes the ID, sort key and title of each category merged into, and the
+    /// manga and category ID of each favourite; entries missing a field can't be matched
+    fn new<'a>(
+        categories: impl IntoIterator<Item = (Option<i64>, Option<i32>, Option<&'a str>)>,
+        favourites: impl IntoIterator<Item = (i64, i64)>,
+    ) -> Self {
+        let mut titles = HashMap::new();
+        let (mut max_id, mut max_sort_key) = (0, 0);
+        for (id, sort_key, title) in categories {
+            max_id = max_id.max(id.unwrap_or(0));
+            max_sort_key = max_sort_key.max(sort_key.unwrap_or(0));
+            if let (Some(id), Some(title)) = (id, title) {
+                titles.entry(title.to_ascii_lowercase()).or_insert(id);
+            }
+        }
+        Self {
+            titles,
+            next_id: max_id + 1,
+            next_sort_key: max_sort_key + 1,
+            ids: HashMap::new(),
+            favourited: favourites.into_iter().collect(),
+        }
+    }
+
+    /// Categories with the same title (case insensitive) are combined; returns the new
+    /// ID and sort key if the category has to be added
+    fn add_category(&mut self, id: i64, title: &str) -> Option<(i64, i32)> {
+        if let Some(&existing) = self.titles.get(&title.to_ascii_lowercase()) {
+            self.ids.insert(id, existing);
+            return None;
+        }
+        let added = (self.next_id, self.next_sort_key);
+        self.titles.insert(title.to_ascii_lowercase(), self.next_id);
+        self.ids.insert(id, self.next_id);
+        self.next_id += 1;
+        self.next_sort_key += 1;
+        Some(added)
+    }
+
+    /// Category ID of the favourite in the merged result, or `None` if it is already there
+    fn add_favourite(&mut self, manga_id: i64, category_id: i64) -> Option<i64> {
+        let category_id = self.ids.get(&category_id).copied().unwrap_or(category_id);
+        self.favourited
+            .insert((manga_id, category_id))
+            .then_some(category_id)
+    }
+}
+
+/// How a source used in a backup resolves to a Kotatsu parser
+#[derive(Debug, Clone)]
+pub struct SourceMatch {
+    pub id: i64,
+    pub name: String,
+    pub base_url: String,
+    /// Matched Kotatsu parser; `None` if the source is unknown or no parser was found
+    pub parser: Option<String>,
+    /// Number of manga in the backup from this source
+    pub manga_count: usize,
+    /// How `parser` was found
+    pub method: Option<MatchMethod>,
+}
+
+/// How a source was matched to its Kotatsu parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMethod {
+    /// Hardcoded in nekotatsu (MangaDex and MangaPlus)
+    Builtin,
+    /// Mapped with [`MangaConverter::with_overrides`] or [`MangaConverter::map_source`]
+    Override,
+    /// Parser name or domain matches the source
+    Normal,
+    /// Parser name matches the extension's package name
+    Package,
+    /// Only matched with soft match on
+    Soft,
+}
+
+/// Source an override applies to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SourceKey {
+    Id(i64),
+    /// Lowercase source name, matching every language of the source
+    Name(String),
+}
+
+impl std::str::FromStr for SourceKey {
+    type Err = std::convert::Infallible;
+
+    /// Numbers are source IDs, anything else is a source name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<i64>() {
+            Ok(id) => Ok(SourceKey::Id(id)),
+            Err(_) => Ok(SourceKey::Name(s.to_lowercase())),
+        }
+    }
+}
+
+impl std::fmt::Display for SourceKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceKey::Id(id) => write!(f, "{id}"),
+            SourceKey::Name(name) => write!(f, "{name}"),
+        }
+    }
 }
 
 impl MangaConverter {
@@ -64,6 +457,34 @@ impl MangaConverter {
             parsers: Vec::new(),
             extensions: extensions::ExtensionList::default(),
             soft_match: false,
+            soft_matched_sources: HashSet::new(),
+            soft_match_confirm: false,
+            soft_match_candidates: HashMap::new(),
+            fail_fast: false,
+            cancel_flag: None,
+            preferred_locale: None,
+            webtoon_scroll: 0,
+            flatten_categories: false,
+            include_unmatched: false,
+            keep_local: false,
+            unknown_state: String::new(),
+            excluded_categories: Vec::new(),
+            category_renames: Vec::new(),
+            convert_tags: true,
+            max_bookmarks: None,
+            skipped_parsers: 0,
+            match_methods: HashMap::new(),
+            builtin_sources: source_ids::BuiltinSources::default(),
+            overrides: HashMap::new(),
+            ignored_overrides: Vec::new(),
+            url_bases: HashMap::new(),
+            source_categories: HashMap::new(),
+            thumbnails: HashMap::from([(
+                String::from("MANGADEX"),
+                config::ThumbnailTransform::Suffix {
+                    suffix: String::from(".256.jpg"),
+                },
+            )]),
         }
     }
 
@@ -86,70 +507,362 @@ impl MangaConverter {
         }
     }
 
-    pub fn try_from_files(mut parsers: File, extensions: File) -> std::io::Result<Self> {
+    /// Soft match without using what it finds; the parsers it would have picked are
+    /// listed in [`MangaConversionResult::soft_match_candidates`] instead
+    pub fn with_soft_match_confirm(self, enabled: bool) -> Self {
+        Self {
+            soft_match: self.soft_match || enabled,
+            soft_match_confirm: enabled,
+            ..self
+        }
+    }
+
+    /// Stop at the first manga that fails to convert instead of skipping it
+    pub fn with_fail_fast(self, enabled: bool) -> Self {
+        Self {
+            fail_fast: enabled,
+            ..self
+        }
+    }
+
+    /// When several parsers match a source, pick the one with this locale (i.e. `en`)
+    pub fn with_preferred_locale(self, locale: Option<String>) -> Self {
+        Self {
+            preferred_locale: locale,
+            ..self
+        }
+    }
+
+    /// Scroll offset for bookmarks and history of manga read in webtoon mode;
+    /// defaults to 0, the top of the last read page
+    pub fn with_webtoon_scroll(self, scroll: i32) -> Self {
+        Self {
+            webtoon_scroll: scroll,
+            ..self
+        }
+    }
+
+    /// Put every manga only in the favorites category instead of recreating the backup's categories
+    pub fn with_flatten_categories(self, enabled: bool) -> Self {
+        Self {
+            flatten_categories: enabled,
+            ..self
+        }
+    }
+
+    /// Convert manga from sources without a Kotatsu parser instead of skipping them,
+    /// using Kotatsu's `UNKNOWN` source and the original URL
+    pub fn with_include_unmatched(self, enabled: bool) -> Self {
+        Self {
+            include_unmatched: enabled,
+            ..self
+        }
+    }
+
+    /// Keep local manga (source ID 0) as entries of Kotatsu's `LOCAL` source with their
+    /// title and cover instead of skipping them
+    pub fn with_keep_local(self, enabled: bool) -> Self {
+        Self {
+            keep_local: enabled,
+            ..self
+        }
+    }
+
+    /// Kotatsu state (e.g. `ONGOING`) for manga whose status is unknown; left empty by default
+    pub fn with_unknown_state(self, state: impl Into<String>) -> Self {
+        Self {
+            unknown_state: state.into(),
+            ..self
+        }
+    }
+
+    /// Leave out the given categories (case insensitive); manga only in excluded categories are skipped
+    pub fn with_excluded_categories(self, names: Vec<String>) -> Self {
+        Self {
+            excluded_categories: names,
+            ..self
+        }
+    }
+
+    /// Rename categories in the converted backup; old names are case insensitive and
+    /// category filters keep using them
+    pub fn with_category_renames(self, renames: Vec<(String, String)>) -> Self {
+        Self {
+            category_renames: renames,
+            ..self
+        }
+    }
+
+    /// Fill manga tags from the backup's genres (the default) or leave them empty
+    pub fn with_convert_tags(self, enabled: bool) -> Self {
+        Self {
+            convert_tags: enabled,
+            ..self
+        }
+    }
+
+    /// Only keep the `limit` most recently read bookmarks of each manga (all by default)
+    pub fn with_max_bookmarks(self, limit: Option<usize>) -> Self {
+        Self {
+            max_bookmarks: limit,
+            ..self
+        }
+    }
+
+    /// Source IDs of MangaDex and MANGA Plus, for forks that don't use Tachiyomi's
+    pub fn with_builtin_sources(self, sources: source_ids::BuiltinSources) -> Self {
+        Self {
+            builtin_sources: sources,
+            ..self
+        }
+    }
+
+    /// Parsers to use for sources instead of matching them, builtin sources included.
+    /// Overrides naming a parser that is not in the parser list are left out and listed
+    /// in [`Self::ignored_overrides`], so set the parsers first
+    pub fn with_overrides(self, overrides: HashMap<SourceKey, String>) -> Self {
+        let (overrides, ignored_overrides) = overrides
+            .into_iter()
+            .partition::<HashMap<_, _>, _>(|(_, parser)| self.has_parser(parser));
+        let mut ignored_overrides = ignored_overrides.into_iter().collect::<Vec<_>>();
+        ignored_overrides.sort_by_key(|(key, _)| key.to_string());
+        Self {
+            overrides,
+            ignored_overrides,
+            ..self
+        }
+    }
+
+    /// Base URLs (i.e. `https://new.example`) of sources that moved domains, keyed like
+    /// [`Self::with_overrides`]; public URLs are built from them instead of the extension's
+    /// base URL and absolute manga and chapter URLs are moved to them
+    pub fn with_url_bases(self, url_bases: HashMap<SourceKey, String>) -> Self {
+        Self { url_bases, ..self }
+    }
+
+    /// Category names keyed like [`Self::with_overrides`]; manga from those sources are
+    /// also added to the named category, which is created if the backup has none by that name
+    pub fn with_source_categories(self, source_categories: HashMap<SourceKey, String>) -> Self {
+        Self {
+            source_categories,
+            ..self
+        }
+    }
+
+    /// How to derive thumbnail URLs from cover URLs per Kotatsu parser, replacing the
+    /// default for MangaDex if given; other parsers use the cover URL as is
+    pub fn with_thumbnails(
+        mut self,
+        thumbnails: HashMap<String, config::ThumbnailTransform>,
+    ) -> Self {
+        self.thumbnails.extend(thumbnails);
+        self
+    }
+
+    /// Abort conversion with [`ConversionError::Cancelled`] once `flag` is set
+    pub fn with_cancel_flag(self, flag: Arc<AtomicBool>) -> Self {
+        Self {
+            cancel_flag: Some(flag),
+            ..self
+        }
+    }
+
+    pub fn try_from_files(parsers: File, extensions: File) -> std::io::Result<Self> {
+        Self::try_from_readers(parsers, extensions)
+    }
+
+    /// Reads the Kotatsu parser list and Tachiyomi extension list as JSON from any reader,
+    /// i.e. `&[u8]` for data that is already in memory
+    pub fn try_from_readers<P: Read, E: Read>(
+        mut parsers: P,
+        extensions: E,
+    ) -> std::io::Result<Self> {
         let mut parser_list = String::new();
         parsers.read_to_string(&mut parser_list)?;
-        let parsers: Vec<KotatsuParser> = serde_json::from_str(&parser_list)
+        let (parsers, skipped_parsers) = parse_parser_list(&parser_list)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        let extensions = extensions::ExtensionList::try_from_file(extensions)?;
+        let extensions = extensions::ExtensionList::try_from_reader(extensions)?;
         let sources = HashMap::new();
 
         Ok(Self {
             sources,
             parsers,
             extensions,
-            soft_match: false,
+            skipped_parsers,
+            ..Self::new()
+        })
+    }
+
+    /// Number of parser list entries that were skipped because they could not be read
+    pub fn skipped_parsers(&self) -> usize {
+        self.skipped_parsers
+    }
+
+    /// Explicitly maps a Tachiyomi source to a Kotatsu parser for the rest of the conversion
+    pub fn map_source(&mut self, source_id: i64, parser_name: &str) {
+        self.sources.insert(source_id, parser_name.to_string());
+        self.match_methods.insert(source_id, MatchMethod::Override);
+    }
+
+    /// Overrides passed to [`Self::with_overrides`] whose parser does not exist
+    pub fn ignored_overrides(&self) -> &[(SourceKey, String)] {
+        &self.ignored_overrides
+    }
+
+    /// How the source was matched to a parser by [`Self::get_source_name`], if it was
+    pub fn match_method(&self, source_id: i64) -> Option<MatchMethod> {
+        if self.override_for(source_id).is_some() {
+            return Some(MatchMethod::Override);
+        }
+        match self.builtin_sources.parser(source_id) {
+            Some(_) => Some(MatchMethod::Builtin),
+            None => self.match_methods.get(&source_id).copied(),
+        }
+    }
+
+    fn override_for(&self, source_id: i64) -> Option<String> {
+        self.source_setting(&self.overrides, source_id).cloned()
+    }
+
+    /// Entry for a source in a map keyed by source ID or name, the ID taking precedence
+    fn source_setting<'a>(
+        &self,
+        settings: &'a HashMap<SourceKey, String>,
+        source_id: i64,
+    ) -> Option<&'a String> {
+        if settings.is_empty() {
+            return None;
+        }
+        settings.get(&SourceKey::Id(source_id)).or_else(|| {
+            let source = self.extensions.get_source(source_id)?;
+            settings.get(&SourceKey::Name(source.name.to_lowercase()))
         })
     }
 
+    /// `url` moved to the base URL set for its source, if any
+    fn rebased_url(&self, source_id: i64, url: &str) -> String {
+        match self.source_setting(&self.url_bases, source_id) {
+            Some(base) => rebase_url(url, base),
+            None => url.to_string(),
+        }
+    }
+
+    pub fn has_parser(&self, parser_name: &str) -> bool {
+        self.parsers.iter().any(|p| p.name == parser_name)
+    }
+
+    /// Kotatsu parsers most likely to correspond to `source`, best first;
+    /// parsers whose domain shares the source's domain name are ranked before
+    /// those compared by edit distance on name and domain
+    pub fn parser_candidates(&self, source: &SourceInfo, count: usize) -> Vec<&KotatsuParser> {
+        let source_name = source.name.to_lowercase();
+        let domain = normalize_domain(&source.baseUrl);
+        let host = domain.split('/').next().unwrap_or_default();
+        let domain_name = host.rsplit_once('.').map_or(host, |(name, _tld)| name);
+
+        let mut ranked = self
+            .parsers
+            .iter()
+            .map(|parser| {
+                let shares_domain = !domain_name.is_empty()
+                    && parser
+                        .domains
+                        .iter()
+                        .any(|d| d.contains(domain_name) || host.contains(d.as_str()));
+                let distance = if shares_domain {
+                    0
+                } else {
+                    parser
+                        .domains
+                        .iter()
+                        .map(|d| edit_distance(host, d))
+                        .chain([
+                            edit_distance(&source_name, &parser.name.to_lowercase()),
+                            edit_distance(&source_name, &parser.title.to_lowercase()),
+                        ])
+                        .min()
+                        .unwrap_or(usize::MAX)
+                };
+                (distance, parser)
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by_key(|(distance, _)| *distance);
+
+        ranked.into_iter().take(count).map(|(_, p)| p).collect()
+    }
+
     pub fn get_source_name(&mut self, manga: &nekotatsu::neko::BackupManga) -> String {
-        match manga.source {
-            // Hardcoded
-            2499283573021220255 => "MANGADEX".to_owned(),
-            1998944621602463790 => "MANGAPLUSPARSER_EN".to_owned(),
+        if let Some(parser) = self.override_for(manga.source) {
+            self.match_methods
+                .insert(manga.source, MatchMethod::Override);
+            return parser;
+        }
 
-            id => {
+        match (manga.source, self.builtin_sources.parser(manga.source)) {
+            (_, Some(parser)) => parser.to_owned(),
+
+            (id, None) => {
                 self.sources
                     .entry(id)
                     .or_insert_with(|| {
                         if let Some(source) = self.extensions.get_source(id) {
-                            let urls = vec![
-                                source
-                                    .baseUrl
-                                    .trim_start_matches("http://")
-                                    .trim_start_matches("https://")
-                                    .to_string(),
-                                source
-                                    .baseUrl
-                                    .trim_start_matches("http://")
-                                    .trim_start_matches("https://")
-                                    .trim_start_matches("www.")
-                                    .to_string(),
-                            ];
-
-                            self.parsers
-                                .iter()
-                                .find(|p| {
+                            let domain = normalize_domain(&source.baseUrl);
+
+                            let locale = self.preferred_locale.as_deref();
+                            let exact_match = prefer_locale(
+                                self.parsers.iter().filter(|p| {
                                     p.name.to_lowercase() == source.name
-                                        || p.domains.iter().any(|d| urls.iter().any(|url| d == url))
-                                })
-                                .or(self
-                                    .soft_match
-                                    .then_some({
-                                        // Boldly assuming that there's only one relevant top-level domain
-                                        let url = source
-                                            .baseUrl
-                                            .trim_start_matches("http://")
-                                            .trim_start_matches("https://");
-                                        match url.rsplit_once(".") {
-                                            Some((name, _tld)) => self.parsers.iter().find(|p| {
+                                        || p.domains.iter().any(|d| normalize_domain(d) == domain)
+                                }),
+                                locale,
+                            )
+                            .map(|p| (p, MatchMethod::Normal))
+                            .or_else(|| {
+                                let pkg = &self.extensions.get_extension(id)?.pkg;
+                                prefer_locale(
+                                    self.parsers
+                                        .iter()
+                                        .filter(|p| parser_matches_package(&p.name, pkg)),
+                                    locale,
+                                )
+                                .map(|p| (p, MatchMethod::Package))
+                            });
+                            let soft_match = match exact_match {
+                                None if self.soft_match => {
+                                    // Boldly assuming that there's only one relevant top-level domain
+                                    let host = domain.split('/').next().unwrap_or_default();
+                                    let found = match host.rsplit_once(".") {
+                                        Some((name, _tld)) => prefer_locale(
+                                            self.parsers.iter().filter(|p| {
                                                 p.domains.iter().any(|d| d.contains(name))
                                             }),
-                                            None => None,
+                                            locale,
+                                        ),
+                                        None => None,
+                                    };
+                                    match found {
+                                        Some(p) if self.soft_match_confirm => {
+                                            self.soft_match_candidates
+                                                .insert(source.name.clone(), p.name.clone());
+                                            None
+                                        }
+                                        Some(p) => {
+                                            self.soft_matched_sources.insert(source.name.clone());
+                                            Some((p, MatchMethod::Soft))
                                         }
-                                    })
-                                    .flatten())
-                                .map_or(String::from("UNKNOWN"), |p| p.name.clone())
+                                        None => None,
+                                    }
+                                }
+                                _ => None,
+                            };
+
+                            match exact_match.or(soft_match) {
+                                Some((parser, method)) => {
+                                    self.match_methods.insert(id, method);
+                                    parser.name.clone()
+                                }
+                                None => String::from("UNKNOWN"),
+                            }
                         } else {
                             String::from("UNKNOWN")
                         }
@@ -159,55 +872,224 @@ impl MangaConverter {
         }
     }
 
+    /// Resolves every source used in `backup` without converting anything,
+    /// sorted by number of manga
+    pub fn match_sources(&mut self, backup: &nekotatsu::neko::Backup) -> Vec<SourceMatch> {
+        let mut matches: Vec<SourceMatch> = Vec::new();
+        for manga in backup.backup_manga.iter() {
+            if let Some(existing) = matches.iter_mut().find(|m| m.id == manga.source) {
+                existing.manga_count += 1;
+                continue;
+            }
+
+            let source = match manga.source {
+                0 => SourceInfo {
+                    name: String::from("Local"),
+                    id: 0.to_string(),
+                    baseUrl: String::new(),
+                    ..Default::default()
+                },
+                id => self.extensions.get_source(id).unwrap_or(SourceInfo {
+                    id: id.to_string(),
+                    ..Default::default()
+                }),
+            };
+            let parser = match manga.source {
+                0 => None,
+                _ if source.name == SourceInfo::default().name => None,
+                _ => Some(self.get_source_name(manga)).filter(|name| name != "UNKNOWN"),
+            };
+
+            let method = parser.as_ref().and(self.match_method(manga.source));
+            matches.push(SourceMatch {
+                id: manga.source,
+                name: source.name,
+                base_url: source.baseUrl,
+                parser,
+                manga_count: 1,
+                method,
+            });
+        }
+
+        matches.sort_by_key(|m| std::cmp::Reverse(m.manga_count));
+        matches
+    }
+
     fn manga_to_kotatsu(
         &mut self,
         manga: &nekotatsu::neko::BackupManga,
     ) -> Option<KotatsuMangaBackup> {
-        let source_info = self.extensions.get_source(manga.source)?;
-        let domain = source_info.baseUrl;
-        let source_name = self.get_source_name(manga);
-        let relative_url = kotatsu::correct_url(&source_name, &manga.url);
+        let (domain, source_name) = match manga.source {
+            0 => (String::new(), String::from(LOCAL_SOURCE)),
+            id => {
+                let base_url = self.extensions.get_source(id)?.baseUrl;
+                (
+                    self.source_setting(&self.url_bases, id)
+                        .map_or(base_url, |base| base.trim_end_matches('/').to_string()),
+                    self.get_source_name(manga),
+                )
+            }
+        };
+        let relative_url =
+            kotatsu::correct_url(&source_name, &self.rebased_url(manga.source, &manga.url));
         let manga_identifier = kotatsu::correct_identifier(&source_name, &relative_url);
 
         Some(KotatsuMangaBackup {
             id: get_kotatsu_id(&source_name, &manga_identifier),
             title: manga.title.clone(),
-            alt_tile: None,
+            // Neko's custom title is the one the user chose to display instead
+            alt_title: Some(manga.custom_title.clone())
+                .filter(|custom| !custom.is_empty() && *custom != manga.title),
             url: relative_url.clone(),
             public_url: format!("{domain}{relative_url}"),
-            rating: -1.0,
+            rating: RATING_UNKNOWN,
             nsfw: false,
-            cover_url: format!("{}.256.jpg", manga.thumbnail_url),
-            large_cover_url: Some(manga.thumbnail_url.clone()),
-            author: manga.author.clone(),
-            state: String::from(match manga.status {
-                1 => "ONGOING",
-                2 | 4 => "FINISHED",
-                5 => "ABANDONED",
-                6 => "PAUSED",
-                _ => "",
-            }),
-            source: source_name.clone(),
-            tags: [],
+            // Kotatsu shows a placeholder for an empty cover URL
+            cover_url: match (
+                manga.thumbnail_url.as_str(),
+                self.thumbnails.get(&source_name),
+            ) {
+                ("", _) => String::new(),
+                (url, Some(transform)) => transform.apply(url),
+                (url, None) => url.to_string(),
+            },
+            large_cover_url: Some(manga.thumbnail_url.clone()).filter(|url| !url.is_empty()),
+            // Kotatsu backups have no artist field, so credit both when they differ
+            author: match (manga.author.trim(), manga.artist.trim()) {
+                (author, "") => author.to_string(),
+                ("", artist) => artist.to_string(),
+                (author, artist) if author.eq_ignore_ascii_case(artist) => author.to_string(),
+                (author, artist) => format!("{author}, {artist}"),
+            },
+            state: match kotatsu_state(manga.status) {
+                "" => self.unknown_state.clone(),
+                state => state.to_string(),
+            },
+            tags: manga
+                .genre
+                .iter()
+                .filter(|_| self.convert_tags)
+                .filter(|genre| !genre.trim().is_empty())
+                .map(|genre| KotatsuTagBackup::from_genre(&source_name, genre))
+                .collect(),
+            source: source_name,
         })
     }
 
+    /// Converts a single manga without its chapters, history or categories
+    pub fn convert_manga(
+        &mut self,
+        manga: &nekotatsu::neko::BackupManga,
+    ) -> Result<KotatsuMangaBackup, ConversionError> {
+        if manga.source == 0 {
+            return match self.keep_local {
+                true => Ok(self
+                    .manga_to_kotatsu(manga)
+                    .expect("local manga always convert")),
+                false => Err(ConversionError::LocalManga {
+                    title: manga.title.clone(),
+                }),
+            };
+        }
+        let Some(source) = self.extensions.get_source(manga.source) else {
+            return Err(ConversionError::UnknownSource {
+                title: manga.title.clone(),
+                source_id: manga.source,
+            });
+        };
+        match self.manga_to_kotatsu(manga) {
+            Some(kotatsu_manga) if kotatsu_manga.source != "UNKNOWN" => Ok(kotatsu_manga),
+            _ => Err(ConversionError::ParserNotFound {
+                title: manga.title.clone(),
+                suggestions: self
+                    .parser_candidates(&source, 3)
+                    .into_iter()
+                    .map(|p| p.name.clone())
+                    .collect(),
+                source_name: source.name,
+                base_url: source.baseUrl,
+            }),
+        }
+    }
+
+    /// Converts every manga in `backup` and returns the Kotatsu backup archive
+    pub fn convert_to_kotatsu_zip(
+        self,
+        backup: nekotatsu::neko::Backup,
+        favorites_name: &str,
+    ) -> std::io::Result<Vec<u8>> {
+        let result = self
+            .convert_backup(backup, favorites_name, &mut Vec::new(), &mut |_, _| true)
+            .map_err(std::io::Error::other)?;
+        Ok(backup::write_kotatsu_zip(&result, std::io::Cursor::new(Vec::new()))?.into_inner())
+    }
+
     pub fn convert_backup(
         mut self,
         backup: nekotatsu::neko::Backup,
         favorites_name: &str,
         logger: &mut dyn Logger,
-        source_filter: &mut dyn FnMut(&SourceInfo) -> bool,
-    ) -> MangaConversionResult {
+        manga_filter: &mut dyn FnMut(&SourceInfo, &nekotatsu::neko::BackupManga) -> bool,
+    ) -> Result<MangaConversionResult, ConversionError> {
         let mut result_categories = Vec::with_capacity(backup.backup_categories.len() + 1);
         let mut result_favourites = Vec::with_capacity(backup.backup_manga.len());
         let mut result_history = Vec::with_capacity(backup.backup_manga.len());
         let mut result_bookmarks = Vec::new();
         let mut errored_sources = HashMap::new();
         let mut errored_sources_count: HashMap<String, usize> = HashMap::new();
+        let mut parser_suggestions: HashMap<String, Vec<String>> = HashMap::new();
         let mut unknown_sources = HashSet::new();
         let mut errored_manga = 0;
         let mut ignored_manga = 0;
+        // Duplicate entries (i.e. from bad merges) share a Kotatsu ID
+        let mut history_index: HashMap<i64, usize> = HashMap::new();
+        let mut favourited: HashSet<(i64, i64)> = HashSet::new();
+        // Chapter numbers of bookmarked chapters, to order bookmarks merged from duplicates
+        let mut bookmark_numbers: HashMap<i64, f32> = HashMap::new();
+        let mut merged_duplicates = 0;
+        let mut included_unmatched = 0;
+        let mut excluded_manga = 0;
+        let mut local_placeholders = 0;
+        let mut failures = Vec::new();
+        let mut tracked_manga = 0;
+
+        for name in self.excluded_categories.iter() {
+            if !backup
+                .backup_categories
+                .iter()
+                .any(|c| c.name.eq_ignore_ascii_case(name))
+            {
+                logger.log_warning(&format!("Category '{name}' to exclude not found in backup"));
+            }
+        }
+        for (old, _) in self.category_renames.iter() {
+            if !backup
+                .backup_categories
+                .iter()
+                .any(|c| c.name.eq_ignore_ascii_case(old))
+            {
+                logger.log_warning(&format!("Category '{old}' to rename not found in backup"));
+            }
+        }
+        // Converted categories are identified by their position in the backup
+        let category_positions: HashMap<i32, usize> =
+            category_references(&backup.backup_categories)
+                .into_iter()
+                .enumerate()
+                .map(|(position, reference)| (reference, position))
+                .collect();
+        let mut dangling_categories = HashSet::new();
+        let excluded_ids: HashSet<usize> = backup
+            .backup_categories
+            .iter()
+            .enumerate()
+            .filter(|(_, category)| {
+                self.excluded_categories
+                    .iter()
+                    .any(|name| category.name.eq_ignore_ascii_case(name))
+            })
+            .map(|(id, _)| id)
+            .collect();
 
         result_categories.push(KotatsuCategoryBackup {
             category_id: CATEGORY_DEFAULT,
@@ -219,49 +1101,103 @@ impl MangaConverter {
             show_in_lib: Some(true),
             deleted_at: 0,
         });
-        result_categories.extend(backup.backup_categories.iter().enumerate().map(
-            |(id, category)| KotatsuCategoryBackup {
-                category_id: id as i64 + CATEGORY_OFFSET,
-                created_at: 0,
-                sort_key: category.order,
-                title: category.name.clone(),
-                order: None,
-                // TODO: convert flags
-                // see https://github.com/mihonapp/mihon/blob/main/domain/src/main/java/tachiyomi/domain/library/model/LibrarySortMode.kt
-                track: None,
-                show_in_lib: Some(true),
-                deleted_at: 0,
-            },
-        ));
+        let categories = if self.flatten_categories {
+            &[][..]
+        } else {
+            &backup.backup_categories[..]
+        };
+        result_categories.extend(
+            categories
+                .iter()
+                .enumerate()
+                .filter(|(id, _)| !excluded_ids.contains(id))
+                .map(|(id, category)| KotatsuCategoryBackup {
+                    category_id: id as i64 + CATEGORY_OFFSET,
+                    created_at: 0,
+                    sort_key: category.order,
+                    title: self
+                        .category_renames
+                        .iter()
+                        .find(|(old, _)| category.name.eq_ignore_ascii_case(old))
+                        .map_or_else(|| category.name.clone(), |(_, new)| new.clone()),
+                    order: None,
+                    // TODO: convert flags
+                    // see https://github.com/mihonapp/mihon/blob/main/domain/src/main/java/tachiyomi/domain/library/model/LibrarySortMode.kt
+                    track: None,
+                    show_in_lib: Some(true),
+                    deleted_at: 0,
+                }),
+        );
 
         for manga in backup.backup_manga.iter() {
-            if manga.source == 0 {
-                logger.log_verbose(&format!(
-                    "[WARNING] Unable to convert '{}', local manga currently unsupported",
-                    manga.title
-                ));
+            if self
+                .cancel_flag
+                .as_ref()
+                .is_some_and(|flag| flag.load(Ordering::Relaxed))
+            {
+                return Err(ConversionError::Cancelled);
+            }
+
+            let manga_categories: Vec<usize> = manga
+                .categories
+                .iter()
+                .filter_map(|reference| {
+                    let position = category_positions.get(reference).copied();
+                    if position.is_none() && dangling_categories.insert(*reference) {
+                        logger.log_warning(&format!(
+                            "'{}' is in category {reference} which is not in the backup; dropping it",
+                            manga.title
+                        ));
+                    }
+                    position
+                })
+                .collect();
+            if !manga_categories.is_empty()
+                && manga_categories.iter().all(|id| excluded_ids.contains(id))
+            {
+                excluded_manga += 1;
+                continue;
+            }
+
+            if manga.source == 0 && !self.keep_local {
+                let error = ConversionError::LocalManga {
+                    title: manga.title.clone(),
+                };
+                if self.fail_fast {
+                    return Err(error);
+                }
+                logger.log_verbose(&format!("[WARNING] {error}"));
+                failures.extend(error.into_failure());
                 errored_manga += 1;
                 continue;
             }
 
-            let source = self
-                .extensions
-                .get_source(manga.source)
-                .unwrap_or(SourceInfo {
-                    id: manga.source.to_string(),
+            let source = match manga.source {
+                0 => SourceInfo {
+                    name: String::from("Local source"),
+                    baseUrl: String::new(),
                     ..Default::default()
-                });
+                },
+                id => self.extensions.get_source(id).unwrap_or(SourceInfo {
+                    id: id.to_string(),
+                    ..Default::default()
+                }),
+            };
 
-            if !source_filter(&source) {
+            if !manga_filter(&source, manga) {
                 ignored_manga += 1;
                 continue;
             }
 
             if source.name == SourceInfo::default().name {
-                let message = format!(
-                    "[WARNING] Unable to convert '{}', unknown Tachiyomi source (ID {})",
-                    manga.title, manga.source
-                );
+                let error = ConversionError::UnknownSource {
+                    title: manga.title.clone(),
+                    source_id: manga.source,
+                };
+                if self.fail_fast {
+                    return Err(error);
+                }
+                let message = format!("[WARNING] {error}");
                 if unknown_sources.contains(&manga.source.to_string()) {
                     logger.log_very_verbose(&message);
                 } else {
@@ -274,6 +1210,7 @@ impl MangaConverter {
                     .entry(source.name.clone())
                     .and_modify(|e| *e += 1)
                     .or_insert(1);
+                failures.extend(error.into_failure());
                 errored_manga += 1;
                 continue;
             }
@@ -282,11 +1219,37 @@ impl MangaConverter {
                 .manga_to_kotatsu(&manga)
                 .expect("unknown Tachiyomi source not filtered");
 
-            if kotatsu_manga.source == "UNKNOWN" {
-                let message = format!(
-                    "[WARNING] Unable to convert '{}' from source {} ({}), Kotatsu parser not found",
-                    manga.title, source.name, source.baseUrl
-                );
+            if kotatsu_manga.source == "UNKNOWN" && self.include_unmatched {
+                logger.log_verbose(&format!(
+                    "Including '{}' from source {} without a Kotatsu parser: {}",
+                    manga.title, source.name, kotatsu_manga.public_url
+                ));
+                included_unmatched += 1;
+            } else if kotatsu_manga.source == LOCAL_SOURCE {
+                logger.log_verbose(&format!(
+                    "Keeping local manga '{}' as a placeholder",
+                    manga.title
+                ));
+                local_placeholders += 1;
+            } else if kotatsu_manga.source == "UNKNOWN" {
+                let suggestions = parser_suggestions
+                    .entry(source.name.clone())
+                    .or_insert_with(|| {
+                        self.parser_candidates(&source, 3)
+                            .into_iter()
+                            .map(|p| p.name.clone())
+                            .collect()
+                    });
+                let error = ConversionError::ParserNotFound {
+                    title: manga.title.clone(),
+                    source_name: source.name.clone(),
+                    base_url: source.baseUrl.clone(),
+                    suggestions: suggestions.clone(),
+                };
+                if self.fail_fast {
+                    return Err(error);
+                }
+                let message = format!("[WARNING] {error}");
                 if errored_sources.contains_key(&source.name) {
                     logger.log_very_verbose(&message)
                 } else {
@@ -297,16 +1260,46 @@ impl MangaConverter {
                     .entry(source.name.clone())
                     .and_modify(|e| *e += 1)
                     .or_insert(1);
+                failures.extend(error.into_failure());
                 errored_manga += 1;
                 continue;
             }
 
+            // Local placeholders have no public URL to check,
+            // and Kotatsu can't open manga without a parser whatever their URL
+            if ![LOCAL_SOURCE, "UNKNOWN"].contains(&kotatsu_manga.source.as_str())
+                && !validate_public_url(&kotatsu_manga.source, &kotatsu_manga.public_url)
+            {
+                logger.log_warning(&format!(
+                    "'{}' was converted but its URL looks wrong and may not open in Kotatsu: {}",
+                    manga.title, kotatsu_manga.public_url
+                ));
+            }
+
+            if !manga.tracking.is_empty() {
+                tracked_manga += 1;
+            }
+
+            let duplicate_of = history_index.get(&kotatsu_manga.id).copied();
+            if duplicate_of.is_some() {
+                logger.log_verbose(&format!(
+                    "Merging duplicate entry of '{}' ({})",
+                    manga.title, manga.url
+                ));
+                merged_duplicates += 1;
+            }
+
+            let source_category = self
+                .source_setting(&self.source_categories, manga.source)
+                .map(|title| category_id_for(&mut result_categories, title));
             result_favourites.extend(
-                manga
-                    .categories
+                manga_categories
                     .iter()
+                    .filter(|id| !self.flatten_categories && !excluded_ids.contains(id))
                     .map(|id| *id as i64 + CATEGORY_OFFSET)
                     .chain(std::iter::once(CATEGORY_DEFAULT))
+                    .chain(source_category)
+                    .filter(|id| favourited.insert((kotatsu_manga.id, *id)))
                     .map(|id| KotatsuFavouriteBackup {
                         manga_id: kotatsu_manga.id.clone(),
                         category_id: id,
@@ -317,67 +1310,117 @@ impl MangaConverter {
                     }),
             );
 
-            let latest_chapter =
+            let last_read = |chapter: &nekotatsu::neko::BackupChapter| {
                 manga
-                    .chapters
+                    .history
                     .iter()
-                    .fold(None, |current, checking| match current {
-                        None if checking.read => Some(checking),
-                        Some(current)
-                            if checking.read
-                                && checking.chapter_number > current.chapter_number =>
-                        {
-                            Some(checking)
-                        }
-                        _ => current,
-                    });
-            let bookmarks: Vec<KotatsuBookmarkEntry> = manga
-                .chapters
-                .iter()
-                .filter_map(|chapter| {
-                    chapter.bookmark.then(|| KotatsuBookmarkEntry {
-                        manga_id: kotatsu_manga.id,
-                        page_id: 0,
-                        chapter_id: get_kotatsu_id(
+                    .filter(|h| h.url == chapter.url)
+                    .map(|h| h.last_read)
+                    .max()
+                    .unwrap_or(0)
+            };
+            // Chapter numbers repeat across scanlators and specials; of those, the most
+            // recently read one wins, then the newest in the source's list
+            let latest_chapter = manga.chapters.iter().filter(|c| c.read).max_by(|a, b| {
+                a.chapter_number
+                    .total_cmp(&b.chapter_number)
+                    .then(last_read(a).cmp(&last_read(b)))
+                    .then(b.source_order.cmp(&a.source_order))
+                    .then(a.date_upload.cmp(&b.date_upload))
+            });
+            // Neko only stores which page was reached, not how far into it
+            let page_scroll = if is_webtoon(manga) {
+                self.webtoon_scroll
+            } else {
+                0
+            };
+            let mut bookmarked: Vec<_> = manga.chapters.iter().filter(|c| c.bookmark).collect();
+            if let Some(limit) = self.max_bookmarks {
+                // Most recently read first, falling back to the latest chapters
+                bookmarked.sort_by(|a, b| {
+                    last_read(b)
+                        .cmp(&last_read(a))
+                        .then(b.chapter_number.total_cmp(&a.chapter_number))
+                });
+                bookmarked.truncate(limit);
+            }
+            let bookmarks: Vec<KotatsuBookmarkEntry> = bookmarked
+                .into_iter()
+                .map(|chapter| {
+                    let chapter_id = get_kotatsu_id(
+                        &kotatsu_manga.source,
+                        &correct_identifier(
                             &kotatsu_manga.source,
-                            &correct_identifier(&kotatsu_manga.source, &chapter.url),
+                            &self.rebased_url(manga.source, &chapter.url),
                         ),
+                    );
+                    bookmark_numbers.insert(chapter_id, chapter.chapter_number);
+                    KotatsuBookmarkEntry {
+                        manga_id: kotatsu_manga.id,
+                        page_id: 0,
+                        chapter_id,
                         page: chapter.last_page_read,
-                        scroll: 0,
+                        scroll: page_scroll,
                         image_url: kotatsu_manga.cover_url.clone(),
-                        created_at: 0,
+                        // Neko doesn't record when a bookmark was made, the last read is closest
+                        created_at: normalize_timestamp(last_read(chapter)),
                         percent: match chapter.last_page_read + chapter.pages_left {
                             0 => 0.0,
                             total_pages => chapter.last_page_read as f32 / total_pages as f32,
                         },
-                    })
+                    }
                 })
                 .collect();
-            if bookmarks.len() > 0 {
+            let existing_bookmarks = result_bookmarks
+                .iter_mut()
+                .find(|entry: &&mut KotatsuBookmarkBackup| entry.manga.id == kotatsu_manga.id);
+            if let Some(existing) = existing_bookmarks {
+                for bookmark in bookmarks {
+                    if !existing
+                        .bookmarks
+                        .iter()
+                        .any(|b| b.chapter_id == bookmark.chapter_id)
+                    {
+                        existing.bookmarks.push(bookmark);
+                    }
+                }
+                if let Some(limit) = self.max_bookmarks {
+                    // Same order as for a single entry, so the most recently read are kept
+                    let number = |b: &KotatsuBookmarkEntry| {
+                        bookmark_numbers.get(&b.chapter_id).copied().unwrap_or(0.0)
+                    };
+                    existing.bookmarks.sort_by(|a, b| {
+                        b.created_at
+                            .cmp(&a.created_at)
+                            .then(number(b).total_cmp(&number(a)))
+                    });
+                    existing.bookmarks.truncate(limit);
+                }
+            } else if bookmarks.len() > 0 {
                 result_bookmarks.push(KotatsuBookmarkBackup {
                     manga: kotatsu_manga.clone(),
                     tags: [],
                     bookmarks,
                 })
             }
-            let newest_cached_chapter = manga
-                .chapters
-                .iter()
-                .max_by(|a, b| a.chapter_number.total_cmp(&b.chapter_number));
             let last_read = manga
                 .history
                 .iter()
                 .max_by(|l, r| l.last_read.cmp(&r.last_read))
-                .map(|entry| entry.last_read)
-                .unwrap_or(manga.last_update);
+                .map(|entry| normalize_timestamp(entry.last_read))
+                .unwrap_or(normalize_timestamp(manga.last_update));
+            let kotatsu_manga_id = kotatsu_manga.id;
             let kotatsu_history = KotatsuHistoryBackup {
                 manga_id: kotatsu_manga.id.clone(),
-                created_at: manga.date_added,
+                created_at: normalize_timestamp(manga.date_added),
                 updated_at: last_read,
                 chapter_id: if let Some(latest) = latest_chapter {
                     get_kotatsu_id(
                         &kotatsu_manga.source,
-                        &correct_identifier(&kotatsu_manga.source, &latest.url),
+                        &correct_identifier(
+                            &kotatsu_manga.source,
+                            &self.rebased_url(manga.source, &latest.url),
+                        ),
                     )
                 } else {
                     0
@@ -385,32 +1428,438 @@ impl MangaConverter {
                 page: latest_chapter
                     .map(|latest| latest.last_page_read)
                     .unwrap_or(0),
-                scroll: 0.0,
-                percent: match (latest_chapter, newest_cached_chapter) {
-                    (Some(latest), Some(newest)) if latest.chapter_number > 0.0 => {
-                        (latest.chapter_number - 1.0) / newest.chapter_number
-                    }
-                    _ => 0.0,
-                },
+                scroll: page_scroll as f32,
+                percent: latest_chapter
+                    .map_or(0.0, |latest| reading_progress(&manga.chapters, latest)),
                 manga: kotatsu_manga,
             };
 
-            result_history.push(kotatsu_history)
+            match duplicate_of {
+                // Keep whichever entry is further along
+                Some(index) => {
+                    let existing: &mut KotatsuHistoryBackup = &mut result_history[index];
+                    if (kotatsu_history.percent, kotatsu_history.updated_at)
+                        > (existing.percent, existing.updated_at)
+                    {
+                        *existing = kotatsu_history;
+                    }
+                }
+                None => {
+                    history_index.insert(kotatsu_manga_id, result_history.len());
+                    result_history.push(kotatsu_history)
+                }
+            }
         }
 
-        MangaConversionResult {
+        Ok(MangaConversionResult {
             categories: result_categories,
             favourites: result_favourites,
             history: result_history,
             bookmarks: result_bookmarks,
             errored_manga,
+            failures,
             errored_sources_count,
+            parser_suggestions,
             unknown_sources,
+            soft_matched_sources: {
+                let mut sources = self.soft_matched_sources.into_iter().collect::<Vec<_>>();
+                sources.sort();
+                sources
+            },
+            soft_match_candidates: {
+                let mut candidates = self.soft_match_candidates.into_iter().collect::<Vec<_>>();
+                candidates.sort();
+                candidates
+            },
             total_manga: backup.backup_manga.len(),
             errored_sources,
             ignored_manga,
+            merged_duplicates,
+            included_unmatched,
+            excluded_manga,
+            local_placeholders,
+            tracked_manga,
+        })
+    }
+}
+
+/// Reading mode bits of `BackupManga::viewer_flags`
+/// see https://github.com/mihonapp/mihon/blob/main/app/src/main/java/eu/kanade/tachiyomi/ui/reader/setting/ReadingMode.kt
+const READING_MODE_MASK: i32 = 0x7;
+
+/// Reading mode chosen for a single manga in Tachiyomi
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadingMode {
+    /// Follows the app-wide setting
+    Default,
+    LeftToRight,
+    RightToLeft,
+    Vertical,
+    Webtoon,
+    ContinuousVertical,
+}
+
+impl ReadingMode {
+    pub fn from_viewer_flags(viewer_flags: i32) -> Self {
+        match viewer_flags & READING_MODE_MASK {
+            0x1 => ReadingMode::LeftToRight,
+            0x2 => ReadingMode::RightToLeft,
+            0x3 => ReadingMode::Vertical,
+            0x4 => ReadingMode::Webtoon,
+            0x5 => ReadingMode::ContinuousVertical,
+            _ => ReadingMode::Default,
+        }
+    }
+
+    /// Name of the Kotatsu reader mode that reads the same way;
+    /// `None` leaves the choice to Kotatsu's automatic detection
+    pub fn kotatsu_mode(self) -> Option<&'static str> {
+        match self {
+            ReadingMode::Default => None,
+            ReadingMode::LeftToRight => Some("STANDARD"),
+            ReadingMode::RightToLeft => Some("REVERSED"),
+            ReadingMode::Vertical => Some("VERTICAL"),
+            ReadingMode::Webtoon | ReadingMode::ContinuousVertical => Some("WEBTOON"),
+        }
+    }
+}
+
+#[test]
+fn reading_mode_from_viewer_flags() {
+    // Upper bits hold the orientation, which Kotatsu has no per-manga setting for
+    assert_eq!(
+        ReadingMode::from_viewer_flags(0x10 | 0x2),
+        ReadingMode::RightToLeft
+    );
+    assert_eq!(
+        ReadingMode::from_viewer_flags(0x5).kotatsu_mode(),
+        Some("WEBTOON")
+    );
+    assert_eq!(ReadingMode::from_viewer_flags(0x7).kotatsu_mode(), None);
+}
+
+/// ID of the category titled `title` (case insensitive), adding it after the others if missing
+fn category_id_for(categories: &mut Vec<KotatsuCategoryBackup>, title: &str) -> i64 {
+    if let Some(existing) = categories
+        .iter()
+        .find(|c| c.title.eq_ignore_ascii_case(title))
+    {
+        return existing.category_id;
+    }
+
+    let category_id = categories.iter().map(|c| c.category_id).max().unwrap_or(0) + 1;
+    categories.push(KotatsuCategoryBackup {
+        category_id,
+        created_at: 0,
+        sort_key: categories.iter().map(|c| c.sort_key).max().unwrap_or(0) + 1,
+        title: title.to_string(),
+        order: None,
+        track: None,
+        show_in_lib: Some(true),
+        deleted_at: 0,
+    });
+    category_id
+}
+
+/// Kotatsu state for each Tachiyomi publishing status (`SManga.status`);
+/// unknown (0) and anything newer have no equivalent
+pub const KOTATSU_STATES: [(i32, &str); 6] = [
+    (1, "ONGOING"),
+    (2, "FINISHED"),
+    // Licensed and taken down from the source
+    (3, "RESTRICTED"),
+    // Publishing finished
+    (4, "FINISHED"),
+    // Cancelled
+    (5, "ABANDONED"),
+    // On hiatus
+    (6, "PAUSED"),
+];
+
+/// Kotatsu's name for a Tachiyomi publishing status; empty if Kotatsu has no equivalent
+pub fn kotatsu_state(status: i32) -> &'static str {
+    KOTATSU_STATES
+        .iter()
+        .find(|(code, _)| *code == status)
+        .map_or("", |(_, state)| state)
+}
+
+#[test]
+fn kotatsu_states_for_every_status() {
+    let states = (0..=7).map(kotatsu_state).collect::<Vec<_>>();
+    assert_eq!(
+        states,
+        [
+            "",
+            "ONGOING",
+            "FINISHED",
+            "RESTRICTED",
+            "FINISHED",
+            "ABANDONED",
+            "PAUSED",
+            ""
+        ]
+    );
+}
+
+/// How far into the manga `latest` is, from 0 to 1; uses chapter numbers when the backup
+/// has them, otherwise the chapter's position in the source's chapter list
+fn reading_progress(
+    chapters: &[nekotatsu::neko::BackupChapter],
+    latest: &nekotatsu::neko::BackupChapter,
+) -> f32 {
+    let newest = chapters
+        .iter()
+        .map(|c| c.chapter_number)
+        .fold(0.0, f32::max);
+    if latest.chapter_number > 0.0 && newest > 0.0 {
+        return (latest.chapter_number - 1.0) / newest;
+    }
+    // Sources list chapters newest first, so every chapter with a higher order came before
+    let earlier = chapters
+        .iter()
+        .filter(|c| c.source_order > latest.source_order)
+        .count();
+    earlier as f32 / chapters.len() as f32
+}
+
+fn is_webtoon(manga: &nekotatsu::neko::BackupManga) -> bool {
+    matches!(
+        ReadingMode::from_viewer_flags(manga.viewer_flags),
+        ReadingMode::Webtoon | ReadingMode::ContinuousVertical
+    )
+}
+
+/// Moves an absolute URL to `base` (i.e. `https://new.example`), keeping its path;
+/// relative URLs are left alone
+pub fn rebase_url(url: &str, base: &str) -> String {
+    match url.split_once("://") {
+        Some((_, rest)) => {
+            let path = rest.find('/').map_or("", |i| &rest[i..]);
+            format!("{}{path}", base.trim_end_matches('/'))
+        }
+        None => url.to_string(),
+    }
+}
+
+#[test]
+fn rebase_urls() {
+    assert_eq!(
+        rebase_url("https://old.example/series/a/1", "https://new.example/"),
+        "https://new.example/series/a/1"
+    );
+    assert_eq!(
+        rebase_url("https://old.example", "https://new.example"),
+        "https://new.example"
+    );
+    assert_eq!(
+        rebase_url("/series/a/1", "https://new.example"),
+        "/series/a/1"
+    );
+}
+
+/// Canonical form of a URL or domain for comparing sources to parsers;
+/// lowercased, without the scheme, trailing slashes or common subdomains like `www.` and `m.`.
+/// Used for every comparison between source and parser domains so they follow the same rules
+pub fn normalize_domain(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let mut domain = url
+        .trim_start_matches("http://")
+        .trim_start_matches("https://")
+        .trim_end_matches('/');
+    while let Some((prefix, rest)) = domain.split_once('.') {
+        let common_prefix = matches!(prefix, "www" | "m" | "mobile")
+            || (prefix.len() > 2
+                && prefix.starts_with("ww")
+                && prefix[2..].bytes().all(|b| b.is_ascii_digit()));
+        // Don't strip down to a bare top-level domain
+        let host = rest.split('/').next().unwrap_or_default();
+        if !common_prefix || !host.contains('.') {
+            break;
+        }
+        domain = rest;
+    }
+
+    domain.to_string()
+}
+
+#[test]
+fn normalize_domains() {
+    assert_eq!(
+        normalize_domain("https://m.mangakakalot.com"),
+        "mangakakalot.com"
+    );
+    assert_eq!(normalize_domain("mangakakalot.com"), "mangakakalot.com");
+    assert_eq!(
+        normalize_domain("https://www.Webtoons.com/"),
+        "webtoons.com"
+    );
+    assert_eq!(normalize_domain("http://ww1.example.org"), "example.org");
+    assert_eq!(
+        normalize_domain("https://toomics.com/en/"),
+        "toomics.com/en"
+    );
+    assert_eq!(normalize_domain("https://m.com"), "m.com");
+    assert_eq!(
+        normalize_domain("https://manga.example.com"),
+        "manga.example.com"
+    );
+}
+
+/// Whether a parser name such as `COMICK_FUN` refers to the same site as an extension package
+/// such as `eu.kanade.tachiyomi.extension.all.comickfun`; the parser's last `_` segment
+/// (usually a top-level domain or locale) may be missing from the package
+fn parser_matches_package(parser_name: &str, pkg: &str) -> bool {
+    let site = pkg.rsplit('.').next().unwrap_or_default();
+    if site.is_empty() {
+        return false;
+    }
+    let name = parser_name.to_lowercase();
+    name.replace('_', "") == site
+        || name
+            .rsplit_once('_')
+            .is_some_and(|(base, _)| base.replace('_', "") == site)
+}
+
+#[test]
+fn match_parser_by_package() {
+    let pkg = |site: &str| format!("eu.kanade.tachiyomi.extension.en.{site}");
+    assert!(parser_matches_package("COMICK_FUN", &pkg("comickfun")));
+    assert!(parser_matches_package("COMICK_FUN", &pkg("comick")));
+    assert!(parser_matches_package("ASURASCANS", &pkg("asurascans")));
+    assert!(!parser_matches_package("ASURASCANS", &pkg("asura")));
+    assert!(!parser_matches_package("MANGADEX", ""));
+}
+
+/// First parser with `locale` if given, otherwise the first parser
+fn prefer_locale<'a>(
+    parsers: impl Iterator<Item = &'a KotatsuParser>,
+    locale: Option<&str>,
+) -> Option<&'a KotatsuParser> {
+    let mut first = None;
+    for parser in parsers {
+        if locale.is_some() && parser.locale.as_deref() == locale {
+            return Some(parser);
+        }
+        first = first.or(Some(parser));
+    }
+    first
+}
+
+/// Values that `BackupManga::categories` uses to refer to each of `categories`.
+/// Tachiyomi and its forks store a category's `order`, but some forks leave orders
+/// unset or duplicated, in which case the position in the backup is used instead
+pub fn category_references(categories: &[nekotatsu::neko::BackupCategory]) -> Vec<i32> {
+    let orders: HashSet<i32> = categories.iter().map(|c| c.order).collect();
+    if orders.len() == categories.len() {
+        categories.iter().map(|c| c.order).collect()
+    } else {
+        (0..categories.len() as i32).collect()
+    }
+}
+
+#[test]
+fn category_references_prefer_order() {
+    use nekotatsu::neko::BackupCategory;
+    let category = |order| BackupCategory {
+        order,
+        ..Default::default()
+    };
+    assert_eq!(
+        category_references(&[category(3), category(1), category(7)]),
+        [3, 1, 7]
+    );
+    assert_eq!(
+        category_references(&[category(0), category(0), category(0)]),
+        [0, 1, 2]
+    );
+}
+
+/// Timestamps below this are assumed to be in seconds rather than milliseconds;
+/// as milliseconds it is in 1973, as seconds it is thousands of years from now
+const SECONDS_TIMESTAMP_LIMIT: i64 = 100_000_000_000;
+
+/// Converts a unix timestamp to the milliseconds Kotatsu expects,
+/// since some Tachiyomi forks store seconds instead
+pub fn normalize_timestamp(timestamp: i64) -> i64 {
+    if timestamp > 0 && timestamp < SECONDS_TIMESTAMP_LIMIT {
+        timestamp * 1000
+    } else {
+        timestamp
+    }
+}
+
+#[test]
+fn normalize_timestamp_units() {
+    assert_eq!(normalize_timestamp(0), 0);
+    assert_eq!(normalize_timestamp(1710000000), 1710000000000);
+    assert_eq!(normalize_timestamp(1710000000000), 1710000000000);
+}
+
+/// Deserializes each entry of a JSON list on its own, leaving out the ones that don't fit `T`
+/// (i.e. from a newer or older data format) instead of rejecting the whole list;
+/// also returns how many were left out
+pub fn parse_lenient<T: serde::de::DeserializeOwned>(
+    entries: Vec<serde_json::Value>,
+) -> (Vec<T>, usize) {
+    let total = entries.len();
+    let parsed = entries
+        .into_iter()
+        .filter_map(|entry| serde_json::from_value(entry).ok())
+        .collect::<Vec<T>>();
+    let skipped = total - parsed.len();
+    (parsed, skipped)
+}
+
+#[test]
+fn parse_lenient_skips_mismatched_entries() {
+    #[derive(serde::Deserialize)]
+    struct Entry {
+        name: String,
+        #[allow(dead_code)]
+        domains: Vec<String>,
+    }
+
+    let entries: Vec<serde_json::Value> = serde_json::from_str(
+        r#"[
+            {"name": "MANGADEX", "domains": ["mangadex.org"]},
+            {"name": "NODOMAINS"},
+            {"name": "NEWFORMAT", "domains": "comick.io"},
+            "not an entry",
+            {"name": "COMICK_FUN", "domains": ["comick.io"], "added_later": true}
+        ]"#,
+    )
+    .unwrap();
+    let (parsed, skipped) = parse_lenient::<Entry>(entries);
+    assert_eq!(
+        parsed.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+        ["MANGADEX", "COMICK_FUN"]
+    );
+    assert_eq!(skipped, 3);
+}
+
+/// Levenshtein distance between two strings
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
         }
     }
+    row[b.len()]
+}
+
+#[test]
+fn edit_distance_counts_single_character_edits() {
+    assert_eq!(edit_distance("", "comick"), 6);
+    assert_eq!(edit_distance("comick", "comick"), 0);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("comick.io", "comick.cc"), 2);
 }
 
 impl Logger for std::io::Stdout {
@@ -420,6 +1869,13 @@ impl Logger for std::io::Stdout {
     }
 }
 
+impl Logger for std::io::Stderr {
+    fn log_info(&mut self, message: &str) {
+        let _ = self.write(message.as_bytes());
+        let _ = self.write(b"\n");
+    }
+}
+
 impl Logger for Vec<String> {
     fn log_info(&mut self, message: &str) -> () {
         self.push(message.to_string());
@@ -430,25 +1886,6 @@ impl Logger for Vec<String> {
     }
 }
 
-fn decode_gzip_backup(file: File) -> std::io::Result<Vec<u8>> {
-    let mut reader = std::io::BufReader::new(file);
-    let mut decoder = flate2::read::GzDecoder::new(&mut reader);
-    let mut buf = Vec::new();
-    decoder.read_to_end(&mut buf)?;
-
-    return Ok(buf);
-}
-
 pub fn decode_neko_backup(file: File) -> std::io::Result<nekotatsu::neko::Backup> {
-    let neko_read = decode_gzip_backup(file)
-        .or_else(|e| {
-            Err(match e.kind() {
-                io::ErrorKind::Interrupted | io::ErrorKind::InvalidInput => io::Error::new(std::io::ErrorKind::InvalidInput,
-                    format!("Error occurred when parsing input archive, is it an actual neko backup? Original error: {e}")
-                ),
-                _ => e
-            })
-        })?;
-
-    Ok(nekotatsu::neko::Backup::decode(&mut neko_read.as_slice())?)
+    backup::BackupReader::read(file)
 }
@@ -0,0 +1,43 @@
+use serde::Deserialize;
+
+/// MangaDex's source ID in Tachiyomi and Mihon
+pub const MANGADEX: i64 = 2499283573021220255;
+/// MANGA Plus (English) source ID in Tachiyomi and Mihon
+pub const MANGAPLUS: i64 = 1998944621602463790;
+
+/// IDs of the sources that are mapped to Kotatsu parsers without looking them up;
+/// forks may use other IDs for the same sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct BuiltinSources {
+    pub mangadex: i64,
+    pub mangaplus: i64,
+}
+
+impl Default for BuiltinSources {
+    fn default() -> Self {
+        Self {
+            mangadex: MANGADEX,
+            mangaplus: MANGAPLUS,
+        }
+    }
+}
+
+impl BuiltinSources {
+    /// Kotatsu parser for `source_id` if it is one of the builtin sources
+    pub fn parser(&self, source_id: i64) -> Option<&'static str> {
+        match source_id {
+            id if id == self.mangadex => Some("MANGADEX"),
+            id if id == self.mangaplus => Some("MANGAPLUSPARSER_EN"),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn override_builtin_sources() {
+    let sources: BuiltinSources = toml::from_str("mangadex = 42").unwrap();
+    assert_eq!(sources.parser(42), Some("MANGADEX"));
+    assert_eq!(sources.parser(MANGADEX), None);
+    assert_eq!(sources.parser(MANGAPLUS), Some("MANGAPLUSPARSER_EN"));
+}
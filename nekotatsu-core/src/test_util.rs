@@ -0,0 +1,111 @@
+//! Builders for neko backups in memory, so conversion can be tested without `.tachibk` fixtures
+
+use crate::nekotatsu::neko::{Backup, BackupCategory, BackupChapter, BackupHistory, BackupManga};
+
+#[derive(Debug, Default)]
+pub struct BackupBuilder {
+    backup: Backup,
+}
+
+impl BackupBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a category ordered after the existing ones; manga refer to it by its order,
+    /// which is its position starting from 0
+    pub fn category(mut self, name: &str) -> Self {
+        let order = self.backup.backup_categories.len() as i32;
+        self.backup.backup_categories.push(BackupCategory {
+            name: name.to_string(),
+            order,
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn manga(mut self, manga: MangaBuilder) -> Self {
+        self.backup.backup_manga.push(manga.build());
+        self
+    }
+
+    pub fn build(self) -> Backup {
+        self.backup
+    }
+}
+
+#[derive(Debug)]
+pub struct MangaBuilder {
+    manga: BackupManga,
+}
+
+impl MangaBuilder {
+    /// Favorited manga from the source with ID `source`
+    pub fn new(source: i64, url: &str, title: &str) -> Self {
+        Self {
+            manga: BackupManga {
+                source,
+                url: url.to_string(),
+                title: title.to_string(),
+                favorite: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn thumbnail(mut self, url: &str) -> Self {
+        self.manga.thumbnail_url = url.to_string();
+        self
+    }
+
+    /// Puts the manga in the category with this order, see [`BackupBuilder::category`]
+    pub fn category(mut self, order: i32) -> Self {
+        self.manga.categories.push(order);
+        self
+    }
+
+    /// Adds a chapter newer than the existing ones; sources list chapters newest first,
+    /// so it gets `source_order` 0 and the others move down
+    pub fn chapter(mut self, url: &str, number: f32) -> Self {
+        for chapter in self.manga.chapters.iter_mut() {
+            chapter.source_order += 1;
+        }
+        self.manga.chapters.push(BackupChapter {
+            url: url.to_string(),
+            name: format!("Chapter {number}"),
+            chapter_number: number,
+            source_order: 0,
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Adds a chapter like [`Self::chapter`] that was read up to `page` at `last_read`
+    /// (unix milliseconds)
+    pub fn read_chapter(self, url: &str, number: f32, page: i32, last_read: i64) -> Self {
+        let mut builder = self.chapter(url, number);
+        let chapter = builder
+            .manga
+            .chapters
+            .last_mut()
+            .expect("chapter was added");
+        chapter.read = true;
+        chapter.last_page_read = page;
+        builder.manga.history.push(BackupHistory {
+            url: url.to_string(),
+            last_read,
+            ..Default::default()
+        });
+        builder
+    }
+
+    /// Edits the manga directly for anything without a builder method
+    pub fn with(mut self, edit: impl FnOnce(&mut BackupManga)) -> Self {
+        edit(&mut self.manga);
+        self
+    }
+
+    pub fn build(self) -> BackupManga {
+        self.manga
+    }
+}
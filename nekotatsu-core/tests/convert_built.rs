@@ -0,0 +1,355 @@
+use nekotatsu_core::{
+    kotatsu::{get_kotatsu_id, KotatsuParser, KotatsuParserContentType},
+    nekotatsu::neko::BackupManga,
+    test_util::{BackupBuilder, MangaBuilder},
+    Logger, MangaConversionResult, MangaConverter,
+};
+
+fn converter() -> MangaConverter {
+    MangaConverter::try_from_readers(
+        include_bytes!("../../tests/fixtures/kotatsu_parsers.json").as_slice(),
+        include_bytes!("../../tests/fixtures/tachi_sources.json").as_slice(),
+    )
+    .expect("fixtures are valid")
+}
+
+#[test]
+fn convert_built_backup() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = BackupBuilder::new()
+        .category("Reading")
+        .category("Done")
+        .manga(
+            MangaBuilder::new(111, "/comic/first", "First")
+                .category(1)
+                .chapter("/comic/first/1", 1.0)
+                .read_chapter("/comic/first/2", 2.0, 4, 1710000000000)
+                .chapter("/comic/first/3", 3.0)
+                .chapter("/comic/first/4", 4.0),
+        )
+        .manga(
+            MangaBuilder::new(111, "/comic/second", "Second")
+                .thumbnail("https://example.org/c.jpg"),
+        )
+        .build();
+
+    let result =
+        converter().convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    assert_eq!(result.errored_manga, 0);
+    assert_eq!(result.categories.len(), 3);
+    let first = result
+        .history
+        .iter()
+        .find(|h| h.manga.title == "First")
+        .unwrap();
+    assert_eq!(first.page, 4);
+    assert_eq!(first.percent, 0.25);
+    assert_eq!(first.updated_at, 1710000000000);
+    assert_eq!(first.manga.cover_url, "");
+    let second = result
+        .history
+        .iter()
+        .find(|h| h.manga.title == "Second")
+        .unwrap();
+    assert_eq!(second.manga.cover_url, "https://example.org/c.jpg");
+
+    Ok(())
+}
+
+#[test]
+fn rename_categories() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = BackupBuilder::new()
+        .category("Reading")
+        .category("Done")
+        .manga(MangaBuilder::new(111, "/comic/first", "First").category(0))
+        .build();
+    let converter = converter().with_category_renames(vec![
+        (String::from("reading"), String::from("Currently Reading")),
+        (String::from("Dropped"), String::from("Gone")),
+    ]);
+
+    let mut log: Vec<String> = Vec::new();
+    let result = converter.convert_backup(backup, "Library", &mut log, &mut |_, _| true)?;
+
+    let titles = result
+        .categories
+        .iter()
+        .map(|c| c.title.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["Library", "Currently Reading", "Done"]);
+    assert!(log
+        .iter()
+        .any(|line| line.contains("Category 'Dropped' to rename not found")));
+
+    Ok(())
+}
+
+#[test]
+fn dangling_category_is_a_warning() -> Result<(), Box<dyn std::error::Error>> {
+    // Only sees what a quiet run would still print
+    struct Warnings(Vec<String>);
+    impl Logger for Warnings {
+        fn log_warning(&mut self, message: &str) {
+            self.0.push(message.to_string());
+        }
+    }
+
+    let backup = BackupBuilder::new()
+        .category("Reading")
+        .manga(MangaBuilder::new(111, "/comic/first", "First").category(5))
+        .build();
+    let mut warnings = Warnings(Vec::new());
+    converter().convert_backup(backup, "Library", &mut warnings, &mut |_, _| true)?;
+
+    assert_eq!(
+        warnings.0,
+        ["'First' is in category 5 which is not in the backup; dropping it"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn merge_results_from_two_backups() -> Result<(), Box<dyn std::error::Error>> {
+    let bookmark_all = |manga: &mut BackupManga| {
+        for chapter in manga.chapters.iter_mut() {
+            chapter.bookmark = true;
+        }
+    };
+    let phone = || {
+        BackupBuilder::new()
+            .category("Reading")
+            .manga(
+                MangaBuilder::new(111, "/comic/shared", "Shared")
+                    .category(0)
+                    .read_chapter("/comic/shared/1", 1.0, 2, 1700000000000)
+                    .with(bookmark_all),
+            )
+            .build()
+    };
+    let tablet = || {
+        BackupBuilder::new()
+            .category("Done")
+            .category("reading")
+            .manga(
+                MangaBuilder::new(111, "/comic/shared", "Shared")
+                    .category(1)
+                    .read_chapter("/comic/shared/1", 1.0, 7, 1710000000000)
+                    .chapter("/comic/shared/2", 2.0)
+                    .with(bookmark_all),
+            )
+            .manga(MangaBuilder::new(111, "/comic/other", "Other").category(0))
+            .build()
+    };
+    let merged = |max_bookmarks| -> Result<_, Box<dyn std::error::Error>> {
+        let mut result =
+            converter().convert_backup(phone(), "Library", &mut Vec::new(), &mut |_, _| true)?;
+        result.merge(
+            converter().convert_backup(tablet(), "Library", &mut Vec::new(), &mut |_, _| true)?,
+            max_bookmarks,
+        );
+        Ok(result)
+    };
+    let result = merged(None)?;
+
+    let titles = result
+        .categories
+        .iter()
+        .map(|c| c.title.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["Library", "Reading", "Done"]);
+    let library = result.categories[0].category_id;
+    let reading = result.categories[1].category_id;
+    let done = result.categories[2].category_id;
+    let shared = result
+        .history
+        .iter()
+        .find(|h| h.manga.title == "Shared")
+        .unwrap();
+    assert_eq!(shared.page, 7);
+    assert_eq!(result.history.len(), 2);
+    assert_eq!(result.merged_duplicates, 1);
+    assert_eq!(result.total_manga, 3);
+    let favourites = result
+        .favourites
+        .iter()
+        .map(|f| (f.manga.title.as_str(), f.category_id))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        favourites,
+        [
+            ("Shared", reading),
+            ("Shared", library),
+            ("Other", done),
+            ("Other", library)
+        ]
+    );
+    let bookmarked = |result: &MangaConversionResult| {
+        result.bookmarks[0]
+            .bookmarks
+            .iter()
+            .map(|b| b.chapter_id)
+            .collect::<Vec<_>>()
+    };
+    let chapter_id = |url: &str| get_kotatsu_id("COMICK_FUN", url);
+    assert_eq!(result.bookmarks.len(), 1);
+    assert_eq!(
+        bookmarked(&result),
+        [chapter_id("/comic/shared/1"), chapter_id("/comic/shared/2")]
+    );
+    // Only the most recently read bookmark is kept
+    assert_eq!(
+        bookmarked(&merged(Some(1))?),
+        [chapter_id("/comic/shared/1")]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unknown_status_fallback() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = BackupBuilder::new()
+        .manga(MangaBuilder::new(111, "/comic/unknown", "Unknown"))
+        .manga(MangaBuilder::new(111, "/comic/hiatus", "Hiatus").with(|m| m.status = 6))
+        .build();
+
+    let result = converter().with_unknown_state("ONGOING").convert_backup(
+        backup,
+        "Library",
+        &mut Vec::new(),
+        &mut |_, _| true,
+    )?;
+
+    let state = |title: &str| {
+        result
+            .history
+            .iter()
+            .find(|h| h.manga.title == title)
+            .map(|h| h.manga.state.as_str())
+    };
+    assert_eq!(state("Unknown"), Some("ONGOING"));
+    assert_eq!(state("Hiatus"), Some("PAUSED"));
+
+    Ok(())
+}
+
+#[test]
+fn soft_match_confirm_lists_candidates() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = || {
+        BackupBuilder::new()
+            .manga(MangaBuilder::new(444, "/series/1", "Bato Manga"))
+            .build()
+    };
+    let converter = || {
+        converter().with_parsers(vec![KotatsuParser {
+            name: String::from("BATO_MIRROR"),
+            title: String::from("Bato mirror"),
+            locale: None,
+            content_type: KotatsuParserContentType::Manga,
+            domains: vec![String::from("bato.si")],
+        }])
+    };
+
+    let soft = converter().with_soft_match(true).convert_backup(
+        backup(),
+        "Library",
+        &mut Vec::new(),
+        &mut |_, _| true,
+    )?;
+    assert_eq!(soft.errored_manga, 0);
+    assert_eq!(soft.soft_matched_sources, ["Bato.to"]);
+
+    let confirm = converter().with_soft_match_confirm(true).convert_backup(
+        backup(),
+        "Library",
+        &mut Vec::new(),
+        &mut |_, _| true,
+    )?;
+    assert_eq!(confirm.errored_manga, 1);
+    assert!(confirm.soft_matched_sources.is_empty());
+    assert_eq!(
+        confirm.soft_match_candidates,
+        [(String::from("Bato.to"), String::from("BATO_MIRROR"))]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn url_base_for_moved_source() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = BackupBuilder::new()
+        .manga(
+            MangaBuilder::new(111, "/comic/moved", "Moved").read_chapter(
+                "https://comick.io/comic/moved/1",
+                1.0,
+                3,
+                1710000000000,
+            ),
+        )
+        .build();
+    let converter = converter().with_url_bases(
+        [("Comick".parse()?, String::from("https://comick.dev/"))]
+            .into_iter()
+            .collect(),
+    );
+
+    let result = converter.convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    let history = &result.history[0];
+    assert_eq!(history.manga.public_url, "https://comick.dev/comic/moved");
+    assert_eq!(
+        history.chapter_id,
+        get_kotatsu_id("COMICK_FUN", "https://comick.dev/comic/moved/1")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn source_categories() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = BackupBuilder::new()
+        .category("Reading")
+        .manga(MangaBuilder::new(111, "/comic/a", "Comick manga").category(0))
+        .manga(MangaBuilder::new(222, "/manga/b", "Kakalot manga"))
+        .build();
+    let converter = converter().with_source_categories(
+        [
+            ("111".parse()?, String::from("reading")),
+            ("Mangakakalot".parse()?, String::from("MK")),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let result = converter.convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    let titles = result
+        .categories
+        .iter()
+        .map(|c| c.title.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["Library", "Reading", "MK"]);
+    let category_of = |id: i64| {
+        result
+            .categories
+            .iter()
+            .find(|c| c.category_id == id)
+            .map(|c| c.title.as_str())
+    };
+    let favourites = result
+        .favourites
+        .iter()
+        .map(|f| (f.manga.title.as_str(), category_of(f.category_id)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        favourites,
+        [
+            ("Comick manga", Some("Reading")),
+            ("Comick manga", Some("Library")),
+            ("Kakalot manga", Some("Library")),
+            ("Kakalot manga", Some("MK")),
+        ]
+    );
+
+    Ok(())
+}
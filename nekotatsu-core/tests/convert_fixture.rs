@@ -0,0 +1,397 @@
+use std::{fs::File, io::Cursor, path::PathBuf};
+
+use nekotatsu_core::{
+    backup::{BackupReader, BackupWriter},
+    decode_neko_backup,
+    kotatsu::{self, get_kotatsu_id, KotatsuCategoryBackup, KotatsuHistoryBackup},
+    nekotatsu::neko::{Backup, BackupHistory},
+    source_ids, ConversionError, ConversionErrorKind, MangaConverter, MatchMethod, SourceKey,
+};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/fixtures");
+
+fn fixture_backup() -> Backup {
+    let file = File::open(PathBuf::from(FIXTURES).join("backup.tachibk")).expect("fixture exists");
+    decode_neko_backup(file).expect("fixture is a valid backup")
+}
+
+fn converter() -> MangaConverter {
+    MangaConverter::try_from_readers(
+        include_bytes!("../../tests/fixtures/kotatsu_parsers.json").as_slice(),
+        include_bytes!("../../tests/fixtures/tachi_sources.json").as_slice(),
+    )
+    .expect("fixtures are valid")
+}
+
+#[test]
+fn convert_fixture_backup() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let result =
+        converter().convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    let mut sources = result
+        .history
+        .iter()
+        .map(|entry| entry.manga.source.as_str())
+        .collect::<Vec<_>>();
+    sources.sort();
+    // Mangakakalot's `m.` subdomain is ignored when matching
+    assert_eq!(
+        sources,
+        ["ASURASCANS", "COMICK_FUN", "MANGADEX", "MANGAKAKALOT"]
+    );
+    assert_eq!(result.total_manga, 7);
+    // Unmatched parser, unknown source and local manga
+    assert_eq!(result.errored_manga, 3);
+    assert!(result.failures.contains(&(
+        String::from("Unknown Manga"),
+        ConversionErrorKind::UnknownSource { source_id: 999 }
+    )));
+    assert!(result
+        .failures
+        .contains(&(String::from("Local Manga"), ConversionErrorKind::LocalManga)));
+    assert_eq!(result.categories.len(), 3);
+
+    // 2024-03-09T16:00:00Z
+    let history = result
+        .history
+        .iter()
+        .find(|h| h.manga.title == "Dex Manga")
+        .unwrap();
+    assert_eq!(history.updated_at, 1710000000000);
+    assert_eq!(history.created_at, 1700000000000);
+
+    Ok(())
+}
+
+#[test]
+fn convert_single_manga() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let mut converter = converter();
+    let find = |title: &str| {
+        backup
+            .backup_manga
+            .iter()
+            .find(|manga| manga.title == title)
+            .expect("manga exists in fixture")
+    };
+
+    let manga = converter.convert_manga(find("Dex Manga"))?;
+    assert_eq!(manga.source, "MANGADEX");
+    assert_eq!(manga.url, "/title/aaaa-bbbb");
+    assert_eq!(manga.id, get_kotatsu_id("MANGADEX", "aaaa-bbbb"));
+    assert_eq!(
+        manga.cover_url,
+        "https://uploads.mangadex.org/covers/aaaa/cover.jpg.256.jpg"
+    );
+
+    let manga = converter.convert_manga(find("Comick Manga"))?;
+    assert_eq!(manga.cover_url, "");
+    assert_eq!(manga.large_cover_url, None);
+
+    // Domains differ, matched through the extension package name instead
+    let manga = converter.convert_manga(find("Asura Manga"))?;
+    assert_eq!(manga.source, "ASURASCANS");
+
+    assert!(matches!(
+        converter.convert_manga(find("Bato Manga")),
+        Err(ConversionError::ParserNotFound { suggestions, .. }) if !suggestions.is_empty()
+    ));
+    assert!(matches!(
+        converter.convert_manga(find("Local Manga")),
+        Err(ConversionError::LocalManga { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn webtoon_bookmark_scroll() -> Result<(), Box<dyn std::error::Error>> {
+    let mut backup = fixture_backup();
+    // Webtoon reading mode
+    backup.backup_manga[0].viewer_flags = 0x4;
+    let converter = converter().with_webtoon_scroll(120);
+
+    let result = converter.convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    let bookmark = &result.bookmarks[0].bookmarks[0];
+    assert_eq!(bookmark.page, 3);
+    assert_eq!(bookmark.scroll, 120);
+    assert_eq!(bookmark.percent, 0.3);
+    let history = result
+        .history
+        .iter()
+        .find(|h| h.manga.title == "Comick Manga")
+        .unwrap();
+    assert_eq!(history.scroll, 0.0);
+
+    Ok(())
+}
+
+#[test]
+fn source_match_methods() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let mut converter = converter();
+    converter.map_source(222, "COMICK_FUN");
+
+    let methods: Vec<(i64, Option<MatchMethod>)> = converter
+        .match_sources(&backup)
+        .iter()
+        .map(|m| (m.id, m.method))
+        .collect();
+    for expected in [
+        (source_ids::MANGADEX, Some(MatchMethod::Builtin)),
+        (111, Some(MatchMethod::Normal)),
+        (222, Some(MatchMethod::Override)),
+        (333, Some(MatchMethod::Package)),
+        (444, None),
+        (999, None),
+    ] {
+        assert!(
+            methods.contains(&expected),
+            "{expected:?} not in {methods:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn overrides_before_matching() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let mut converter = converter().with_overrides(
+        [
+            (SourceKey::Id(source_ids::MANGADEX), "COMICK_FUN"),
+            ("bato.TO".parse()?, "ASURASCANS"),
+            (SourceKey::Id(111), "NOT_A_PARSER"),
+        ]
+        .into_iter()
+        .map(|(key, parser)| (key, parser.to_string()))
+        .collect(),
+    );
+    assert_eq!(
+        converter.ignored_overrides(),
+        [(SourceKey::Id(111), "NOT_A_PARSER".to_string())]
+    );
+
+    let matches: Vec<(i64, Option<String>, Option<MatchMethod>)> = converter
+        .match_sources(&backup)
+        .into_iter()
+        .map(|m| (m.id, m.parser, m.method))
+        .collect();
+    for expected in [
+        (
+            source_ids::MANGADEX,
+            Some("COMICK_FUN".to_string()),
+            Some(MatchMethod::Override),
+        ),
+        (
+            444,
+            Some("ASURASCANS".to_string()),
+            Some(MatchMethod::Override),
+        ),
+        (
+            111,
+            Some("COMICK_FUN".to_string()),
+            Some(MatchMethod::Normal),
+        ),
+    ] {
+        assert!(
+            matches.contains(&expected),
+            "{expected:?} not in {matches:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn limit_bookmarks_per_manga() -> Result<(), Box<dyn std::error::Error>> {
+    let mut backup = fixture_backup();
+    for chapter in backup.backup_manga[0].chapters.iter_mut() {
+        chapter.bookmark = true;
+    }
+    // A duplicate entry whose bookmark was read after any of the first entry's
+    let mut duplicate = backup.backup_manga[0].clone();
+    let mut chapter = duplicate.chapters[0].clone();
+    chapter.url = String::from("c9");
+    chapter.chapter_number = 0.5;
+    duplicate.chapters = vec![chapter];
+    duplicate.history = vec![BackupHistory {
+        url: String::from("c9"),
+        last_read: 4102444800000,
+        ..Default::default()
+    }];
+    backup.backup_manga.push(duplicate);
+    let converter = converter().with_max_bookmarks(Some(2));
+
+    let result = converter.convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    let manga = &result.bookmarks[0].manga;
+    let chapter_ids: Vec<i64> = result.bookmarks[0]
+        .bookmarks
+        .iter()
+        .map(|b| b.chapter_id)
+        .collect();
+    // c9 was read last, then c2 is the only other chapter with history
+    assert_eq!(
+        chapter_ids,
+        vec![
+            get_kotatsu_id(&manga.source, "c9"),
+            get_kotatsu_id(&manga.source, "c2")
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn progress_without_chapter_numbers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut backup = fixture_backup();
+    // Read up to the second of three unnumbered chapters, listed newest first by the source
+    for (chapter, source_order) in backup.backup_manga[0].chapters.iter_mut().zip([2, 1, 0]) {
+        chapter.chapter_number = 0.0;
+        chapter.source_order = source_order;
+        chapter.read = source_order == 1;
+    }
+    let result =
+        converter().convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    let history = result
+        .history
+        .iter()
+        .find(|h| h.manga.title == "Dex Manga")
+        .unwrap();
+    assert_eq!(history.percent, 1.0 / 3.0);
+
+    Ok(())
+}
+
+#[test]
+fn latest_read_with_duplicate_chapter_numbers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut backup = fixture_backup();
+    // Two groups' releases of chapter 2, the second one listed as newer by the source
+    let manga = &mut backup.backup_manga[0];
+    manga.history.clear();
+    for (chapter, (source_order, page)) in manga.chapters.iter_mut().zip([(2, 5), (1, 9), (0, 0)]) {
+        chapter.chapter_number = 2.0;
+        chapter.source_order = source_order;
+        chapter.read = source_order > 0;
+        chapter.last_page_read = page;
+    }
+    let result =
+        converter().convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    let history = result
+        .history
+        .iter()
+        .find(|h| h.manga.title == "Dex Manga")
+        .unwrap();
+    assert_eq!(history.page, 9);
+
+    Ok(())
+}
+
+#[test]
+fn keep_local_manga() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let converter = converter().with_keep_local(true);
+
+    let mut log: Vec<String> = Vec::new();
+    let result = converter.convert_backup(backup, "Library", &mut log, &mut |_, _| true)?;
+
+    assert_eq!(result.local_placeholders, 1);
+    // Only the Asura fixture URL is flagged, the placeholder has no public URL
+    assert!(!log
+        .iter()
+        .any(|line| line.contains("'Local Manga' was converted but its URL looks wrong")));
+    assert_eq!(result.errored_manga, 2);
+    let local = result
+        .history
+        .iter()
+        .find(|h| h.manga.source == kotatsu::LOCAL_SOURCE)
+        .expect("local manga is kept");
+    assert!(!local.manga.title.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn include_unmatched_manga() -> Result<(), Box<dyn std::error::Error>> {
+    let mut backup = fixture_backup();
+    let bato = backup
+        .backup_manga
+        .iter_mut()
+        .find(|manga| manga.title == "Bato Manga")
+        .expect("manga exists in fixture");
+    // Would be flagged if the manga had a parser
+    bato.url = String::new();
+    let converter = converter().with_include_unmatched(true);
+
+    let mut log: Vec<String> = Vec::new();
+    let result = converter.convert_backup(backup, "Library", &mut log, &mut |_, _| true)?;
+
+    assert_eq!(result.included_unmatched, 1);
+    assert!(!log
+        .iter()
+        .any(|line| line.contains("'Bato Manga' was converted but its URL looks wrong")));
+
+    Ok(())
+}
+
+#[test]
+fn exclude_category() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let converter = converter().with_excluded_categories(vec!["completed".into()]);
+
+    let result = converter.convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+
+    assert_eq!(result.excluded_manga, 1);
+    assert!(result
+        .history
+        .iter()
+        .all(|h| h.manga.title != "Comick Manga"));
+    assert!(result.categories.iter().all(|c| c.title != "Completed"));
+    Ok(())
+}
+
+#[test]
+fn merge_into_existing_backup() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let result =
+        converter().convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)?;
+    let existing = BackupWriter::new(&result).to_bytes(&mut Vec::new())?;
+
+    // Merging a backup into itself changes nothing
+    let merged = BackupWriter::new(&result)
+        .merge_into(Cursor::new(existing))?
+        .to_bytes(&mut Vec::new())?;
+    let mut archive = BackupReader::open_kotatsu(Cursor::new(merged))?;
+    let favourites: Vec<serde_json::Value> =
+        serde_json::from_reader(archive.by_name("favourites")?)?;
+    assert_eq!(favourites.len(), result.favourites.len());
+    let categories: Vec<serde_json::Value> =
+        serde_json::from_reader(archive.by_name("categories")?)?;
+    assert_eq!(categories.len(), result.categories.len());
+    Ok(())
+}
+
+#[test]
+fn kotatsu_zip_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+    let backup = fixture_backup();
+    let zipped = converter().convert_to_kotatsu_zip(backup, "Library")?;
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(zipped))?;
+    let mut names = archive.file_names().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        ["bookmarks", "categories", "favourites", "history", "index"]
+    );
+    let history: Vec<KotatsuHistoryBackup> = serde_json::from_reader(archive.by_name("history")?)?;
+    assert_eq!(history.len(), 4);
+    let categories: Vec<KotatsuCategoryBackup> =
+        serde_json::from_reader(archive.by_name("categories")?)?;
+    assert_eq!(categories[0].title, "Library");
+    Ok(())
+}
@@ -1,24 +1,44 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use directories::ProjectDirs;
 use flate2::{write::GzEncoder, Compression};
 use prost::Message;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::{self, Write},
-    path::PathBuf,
-    sync::LazyLock,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, LazyLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
+use crate::nekotatsu_core::backup::{repair_kotatsu, BackupReader, BackupWriter};
 use crate::nekotatsu_core::config::SourceFilterList;
 use crate::nekotatsu_core::kotatsu::{self, *};
 use crate::nekotatsu_core::*;
 
+/// Environment variable that relocates the data directory, e.g. for portable installs
+pub const DATA_DIR_ENV: &str = "NEKOTATSU_DATA_DIR";
+
 static PROJECT_DIR: LazyLock<ProjectDirs> =
     LazyLock::new(|| ProjectDirs::from("", "", "Nekotatsu").expect("home directory should exist"));
+static DATA_DIR_OVERRIDE: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
+    std::env::var_os(DATA_DIR_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+});
+static DATA_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    DATA_DIR_OVERRIDE
+        .clone()
+        .unwrap_or_else(|| PROJECT_DIR.data_dir().into())
+});
 static DEFAULT_TACHI_SOURCE_PATH: LazyLock<PathBuf> =
-    LazyLock::new(|| PROJECT_DIR.data_dir().join("tachi_sources.json").into());
+    LazyLock::new(|| DATA_DIR.join("tachi_sources.json"));
 static DEFAULT_KOTATSU_PARSE_PATH: LazyLock<PathBuf> =
-    LazyLock::new(|| PROJECT_DIR.data_dir().join("kotatsu_parsers.json").into());
+    LazyLock::new(|| DATA_DIR.join("kotatsu_parsers.json"));
+static DEFAULT_KOTATSU_REPO_PATH: LazyLock<PathBuf> =
+    LazyLock::new(|| DATA_DIR.join("kotatsu-parsers.zip"));
 
 /// Simple CLI tool that converts Neko backups into Kotatsu backups
 #[derive(Debug, Parser)]
@@ -28,17 +48,30 @@ pub struct Args {
     pub command: Option<Commands>,
 }
 
+// Parsed once per run; boxing `Convert` would only complicate constructing it in frontends
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Convert a Neko/Tachiyomi backup into one that Kotatsu can read
     Convert {
-        /// Path to Neko/Tachi backup
+        /// Path or http(s) URL to Neko/Tachi backup, or an Aidoku JSON backup;
+        /// use `-` to read the backup from stdin
         input: String,
 
-        /// Optional output name
+        /// More Neko/Tachi or Aidoku backups to convert into the same Kotatsu backup;
+        /// categories with the same name are combined
+        more_inputs: Vec<String>,
+
+        /// Optional output name; use `-` to write the converted backup to stdout
+        /// (Kotatsu backups only)
         #[arg(short, long)]
         output: Option<String>,
 
+        /// Directory to write the output to; without `--output`,
+        /// the file is named after the input
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+
         /// Category name for favorited manga.
         #[arg(alias("fn"), long, default_value_t = String::from("Library"))]
         favorites_name: String,
@@ -51,21 +84,172 @@ pub enum Commands {
         #[arg(short('V'), long)]
         very_verbose: bool,
 
+        /// Only display warnings, errors and the output path
+        #[arg(short, long, conflicts_with_all = ["verbose", "very_verbose"])]
+        quiet: bool,
+
         /// Convert to Neko instead
         #[arg(short, long)]
         reverse: bool,
 
-        /// Strip top-level domains when comparing Tachiyomi/Mihon sources to Kotatsu parsers
-        #[arg(short, long)]
-        soft_match: bool,
+        /// Format of the input backup, regardless of its name; sets the conversion direction
+        #[arg(long, value_enum, conflicts_with = "reverse")]
+        input_format: Option<BackupFormat>,
+
+        /// Format to convert to; sets the conversion direction like `--input-format`
+        #[arg(long, value_enum, conflicts_with = "reverse")]
+        output_format: Option<BackupFormat>,
+
+        /// Gzip compression level used when converting to Neko [default: fast]
+        #[arg(long, value_enum)]
+        compression: Option<CompressionLevel>,
+
+        /// Strip top-level domains when comparing Tachiyomi/Mihon sources to Kotatsu parsers;
+        /// with `confirm`, only list what it finds as candidates for the `overrides` config
+        #[arg(short, long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "on")]
+        soft_match: Option<SoftMatchMode>,
 
-        /// Convert without asking about overwriting existing files
+        /// Prefer Kotatsu parsers with this locale (i.e. `en`) when several match a source
+        #[arg(long)]
+        lang: Option<String>,
+
+        /// Convert without asking about overwriting existing files; same as `--overwrite-policy overwrite`
         #[arg(short, long)]
         force: bool,
 
+        /// What to do when the output file already exists [default: ask]
+        #[arg(long, value_enum)]
+        overwrite_policy: Option<OverwritePolicy>,
+
         #[arg(short, long)]
         config_file: Option<PathBuf>,
 
+        /// Use this Kotatsu parser list instead of the one downloaded by `update`
+        #[arg(long)]
+        parsers: Option<PathBuf>,
+
+        /// Use this Tachiyomi source list instead of the one downloaded by `update`
+        #[arg(long)]
+        sources: Option<PathBuf>,
+
+        /// Only convert manga from this source name, URL or ID; can be given multiple times
+        /// and replaces the config file's whitelist
+        #[arg(long)]
+        whitelist: Vec<config::SourceFilterEntry>,
+
+        /// Skip manga from this source name, URL or ID; can be given multiple times
+        /// and replaces the config file's blacklist
+        #[arg(long)]
+        blacklist: Vec<config::SourceFilterEntry>,
+
+        /// In-memory config for frontends; takes precedence over `config_file`
+        #[arg(skip)]
+        config: Option<config::ConfigFile>,
+
+        /// Flag for frontends to cancel an in-progress conversion
+        #[arg(skip)]
+        cancel_flag: Option<Arc<AtomicBool>>,
+
+        /// Only convert manga added on or after this date;
+        /// accepts a date (`YYYY-MM-DD`) or a unix timestamp in milliseconds
+        #[arg(long, value_parser = parse_timestamp)]
+        since: Option<i64>,
+
+        /// Only convert manga in this category; can be given multiple times
+        #[arg(long)]
+        category: Vec<String>,
+
+        /// Leave out this category and manga only in it; can be given multiple times
+        #[arg(long)]
+        exclude_category: Vec<String>,
+
+        /// Rename a category in the converted backup, given as `old=new`; can be given multiple times
+        #[arg(long, value_parser = parse_category_rename)]
+        rename_category: Vec<(String, String)>,
+
+        /// Base URL a source moved to, given as `source=url` with the source's ID or name,
+        /// i.e. `Asura Scans=https://asuracomic.net`; public URLs and absolute manga and chapter
+        /// URLs use it instead of the old domain. Can be given multiple times
+        #[arg(long, value_parser = parse_url_base)]
+        chapter_url_base: Vec<(String, String)>,
+
+        /// Also add manga from a source to a category, given as `source=category` with the
+        /// source's ID or name; the category is created if needed. Can be given multiple times
+        #[arg(long, value_parser = parse_source_category)]
+        favorites_category: Vec<(String, String)>,
+
+        /// Put every manga in the favorites category instead of keeping the backup's categories
+        #[arg(long)]
+        flatten_categories: bool,
+
+        /// Skip manga whose chapters are all read
+        #[arg(long)]
+        strip_read: bool,
+
+        /// Where to take manga tags from [default: genre]
+        #[arg(long, value_enum)]
+        tags_from: Option<TagSource>,
+
+        /// Only keep this many of the most recently read bookmarks per manga
+        #[arg(long)]
+        max_bookmarks: Option<usize>,
+
+        /// Abort on the first manga that fails to convert instead of skipping it
+        #[arg(long, overrides_with = "keep_going")]
+        fail_fast: bool,
+
+        /// Skip manga that fail to convert and report them at the end (default)
+        #[arg(long, overrides_with = "fail_fast")]
+        keep_going: bool,
+
+        /// Ask which Kotatsu parser to use for each source that has no match
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// Convert manga from sources without a Kotatsu parser anyway,
+        /// keeping their original URL under Kotatsu's unknown source
+        #[arg(long)]
+        include_unmatched: bool,
+
+        /// Keep local manga as placeholder entries of Kotatsu's local source with their
+        /// title and cover; their files have to be imported into Kotatsu separately
+        #[arg(long)]
+        keep_local: bool,
+
+        /// Kotatsu state for manga whose status is unknown; left empty by default
+        #[arg(long, ignore_case = true, value_parser = ["ONGOING", "FINISHED", "ABANDONED", "PAUSED", "UPCOMING", "RESTRICTED"])]
+        unknown_state: Option<String>,
+
+        /// Log the Kotatsu source and URL of every converted manga
+        #[arg(long)]
+        list_converted: bool,
+
+        /// Write the names and URLs of sources without a Kotatsu parser to this file,
+        /// one tab separated pair per line
+        #[arg(long)]
+        unmatched_out: Option<PathBuf>,
+
+        /// Write a JSON report of the sources that failed to convert, split into sources
+        /// that have no Kotatsu parser yet and source IDs missing from the extension list,
+        /// along with why each manga failed
+        #[arg(long)]
+        report_missing_parsers: Option<PathBuf>,
+
+        /// Write pre-filled failing conversion issues (Markdown) for the sources without
+        /// a Kotatsu parser to this file, or to stdout with '-' (stderr if the backup
+        /// itself is written to stdout)
+        #[arg(long)]
+        issue_template: Option<PathBuf>,
+
+        /// Add the converted manga to this existing Kotatsu backup instead of creating a fresh one;
+        /// the combined backup is written to the output path
+        #[arg(long, conflicts_with = "reverse")]
+        merge_into: Option<PathBuf>,
+
+        /// Kotatsu version code written to the backup's index [default: 0, accepted by all current releases]
+        #[arg(long, conflicts_with = "reverse")]
+        target_version: Option<u64>,
+
         #[arg(long, hide = true, default_value_t = true)]
         print_output: bool,
     },
@@ -74,6 +258,7 @@ pub enum Commands {
     /// updates Kotatsu parser list. The resulting files are saved in the app's data directory
     /// (`~/.local/share/nekotatsu` on Linux and `%APPDATA%\Nekotatsu\data` on Windows)
     /// as `tachi_sources.json` and `kotatsu_parsers.json`.
+    /// Set `NEKOTATSU_DATA_DIR` to use a different directory.
     Update {
         /// Download URL for Kotatsu parsers repo.
         #[arg(short, long, default_value_t = String::from("https://github.com/KotatsuApp/kotatsu-parsers/archive/refs/heads/master.zip"))]
@@ -86,8 +271,101 @@ pub enum Commands {
         /// Force download of files even if they already exist
         #[arg(short, long)]
         force_download: bool,
+
+        /// Also print how many parsers were found of each content type
+        #[arg(long)]
+        parser_stats: bool,
+
+        /// Proxy for the downloads, i.e. `http://127.0.0.1:8080`;
+        /// defaults to the HTTP_PROXY and HTTPS_PROXY environment variables
+        #[arg(long)]
+        proxy: Option<String>,
+
+        /// Download progress shared with frontends
+        #[arg(skip)]
+        progress: Option<Arc<DownloadProgress>>,
+    },
+
+    /// Print the data directory and the status of the files downloaded by `update`
+    Info,
+
+    /// List the sources used in a backup and the Kotatsu parser each one matches
+    ListSources {
+        /// Path to Neko/Tachi backup
+        input: String,
+
+        /// Strip top-level domains when comparing Tachiyomi/Mihon sources to Kotatsu parsers
+        #[arg(short, long)]
+        soft_match: bool,
+    },
+
+    /// Write the Kotatsu parser each source in a backup resolves to, and how it was matched,
+    /// as JSON keyed by source ID; handy to attach to issues
+    MatchMap {
+        /// Path to Neko/Tachi backup
+        input: String,
+
+        /// File to write the JSON to instead of printing it
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Strip top-level domains when comparing Tachiyomi/Mihon sources to Kotatsu parsers
+        #[arg(short, long)]
+        soft_match: bool,
+
+        /// Config file with source overrides to apply
+        #[arg(short, long)]
+        config_file: Option<PathBuf>,
+    },
+
+    /// Write a plain list of the manga in a backup, with their title, source,
+    /// author, status and URL
+    Export {
+        /// Path to Neko/Tachi backup, or an Aidoku JSON backup
+        input: String,
+
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// File to write the list to instead of printing it
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Fix manga URLs and IDs in a Kotatsu backup converted by an older version of nekotatsu,
+    /// without converting it again
+    Repair {
+        /// Path to the Kotatsu backup
+        input: PathBuf,
+
+        /// Where to write the repaired backup [default: `<input>_repaired.zip`]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Replace the output file if it already exists
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Print the Kotatsu manga and chapter IDs nekotatsu computes for a URL, after applying
+    /// the same corrections as `convert`; entries only link up in Kotatsu if their IDs match
+    ComputeId {
+        /// Kotatsu parser name, i.e. `MANGADEX`
+        source: String,
+
+        /// Manga or chapter URL as stored in the Neko/Tachi backup
+        url: String,
     },
 
+    /// Search the Kotatsu parser list by name, title or domain
+    SearchParsers {
+        /// Text to look for (case-insensitive)
+        query: String,
+    },
+
+    /// Check the downloaded data files for common causes of sources failing to match
+    Doctor,
+
     /// Output backup info
     #[command(hide(true))]
     Debug { input: String },
@@ -99,8 +377,119 @@ pub enum Commands {
     Delete,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SoftMatchMode {
+    /// Use soft matches like any other match
+    On,
+    /// Leave soft-matched sources unconverted and list them as candidate overrides
+    Confirm,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompressionLevel {
+    Fast,
+    Default,
+    Best,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OverwritePolicy {
+    /// Ask before overwriting
+    Ask,
+    /// Replace the existing file
+    Overwrite,
+    /// Leave the existing file alone and don't convert
+    Skip,
+    /// Write to a new file with a numeric suffix, e.g. `neko_converted_1.zip`
+    Rename,
+}
+
+/// The input's file name with its backup extension (`.tachibk`, `.proto.gz`, `.zip`)
+/// replaced by `extension`; dots elsewhere in the name, e.g. Mihon's `app.mihon_…`, are kept
+fn output_name_for_input(input: &Path, extension: &str) -> String {
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = stem.strip_suffix(".proto").unwrap_or(&stem);
+    format!("{stem}.{extension}")
+}
+
+/// First path of the form `{stem}_{n}.{extension}` that doesn't exist yet
+fn numbered_output_path(path: &std::path::Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string());
+    (1..)
+        .map(|n| match &extension {
+            Some(extension) => path.with_file_name(format!("{stem}_{n}.{extension}")),
+            None => path.with_file_name(format!("{stem}_{n}")),
+        })
+        .find(|candidate| !candidate.exists())
+        .expect("some numbered path is free")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackupFormat {
+    /// Neko/Tachiyomi/Mihon backup (`.tachibk`)
+    Neko,
+    /// Kotatsu backup (`.zip`)
+    Kotatsu,
+}
+
+impl BackupFormat {
+    fn name(self) -> &'static str {
+        match self {
+            BackupFormat::Neko => "Neko/Tachiyomi",
+            BackupFormat::Kotatsu => "Kotatsu",
+        }
+    }
+
+    /// Format of the backup at `path` judging by its first bytes; Aidoku JSON backups
+    /// count as Neko since they are converted the same way
+    fn detect(path: &Path) -> Option<Self> {
+        let mut magic = [0; 4];
+        let read = io::Read::read(&mut std::fs::File::open(path).ok()?, &mut magic).ok()?;
+        match &magic[..read] {
+            [b'P', b'K', 3, 4] => Some(BackupFormat::Kotatsu),
+            [0x1f, 0x8b, ..] | [b'{', ..] => Some(BackupFormat::Neko),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TagSource {
+    /// The genres stored in the backup
+    Genre,
+    /// Leave tags empty
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl From<CompressionLevel> for Compression {
+    fn from(level: CompressionLevel) -> Self {
+        match level {
+            CompressionLevel::Fast => Compression::fast(),
+            CompressionLevel::Default => Compression::default(),
+            CompressionLevel::Best => Compression::best(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum CommandVerbosity {
+    /// Only warnings, errors and the output path
+    Quiet,
     None,
     Verbose,
     VeryVerbose,
@@ -110,32 +499,583 @@ pub enum CommandVerbosity {
 pub enum CommandResult {
     None,
     Success(String, String),
+    /// Output was written but some manga failed to convert
+    Incomplete {
+        path: String,
+        output: String,
+        errored: usize,
+    },
 }
 
-fn neko_to_kotatsu_command(
-    input_path: String,
-    output_path: PathBuf,
+impl CommandResult {
+    /// Process exit code for the CLI; non-zero when some manga failed to convert
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CommandResult::None | CommandResult::Success(_, _) => 0,
+            CommandResult::Incomplete { .. } => 2,
+        }
+    }
+}
+
+/// Bytes received for the file `update` is currently downloading
+#[derive(Debug, Default)]
+pub struct DownloadProgress {
+    pub downloaded: AtomicU64,
+    /// Zero if the server did not report a length
+    pub total: AtomicU64,
+}
+
+/// Downloads `url` in chunks, printing progress and an estimate of the remaining time
+/// when the server reports the file size. Without `proxy`, the proxy from the
+/// `HTTP_PROXY`/`HTTPS_PROXY` environment variables is used, if any
+fn download_with_progress(
+    url: &str,
+    label: &str,
+    proxy: Option<&str>,
+    progress: Option<&DownloadProgress>,
+    out: &mut dyn Write,
+) -> io::Result<Vec<u8>> {
+    let mut client = reqwest::blocking::Client::builder();
+    if let Some(proxy) = proxy {
+        client = client.proxy(reqwest::Proxy::all(proxy).map_err(io::Error::other)?);
+    }
+    let mut response = client
+        .build()
+        .and_then(|client| client.get(url).send())
+        .map_err(io::Error::other)?;
+    let total = response.content_length().unwrap_or(0);
+    if let Some(progress) = progress {
+        progress.downloaded.store(0, Ordering::Relaxed);
+        progress.total.store(total, Ordering::Relaxed);
+    }
+
+    let started = Instant::now();
+    let mut body = Vec::with_capacity(total as usize);
+    let mut chunk = vec![0; 64 * 1024];
+    let mut last_printed = 0;
+    loop {
+        let read = match io::Read::read(&mut response, &mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        body.extend_from_slice(&chunk[..read]);
+        let downloaded = body.len() as u64;
+        if let Some(progress) = progress {
+            progress.downloaded.store(downloaded, Ordering::Relaxed);
+        }
+
+        // Only redraw every 512 KiB
+        if downloaded - last_printed < 512 * 1024 {
+            continue;
+        }
+        last_printed = downloaded;
+        let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        if total > 0 {
+            let elapsed = started.elapsed().as_secs_f64();
+            let remaining = elapsed * (total.saturating_sub(downloaded)) as f64 / downloaded as f64;
+            write!(
+                out,
+                "\rDownloading {label}: {:.1}/{:.1} MiB ({}%), about {}s left   ",
+                mib(downloaded),
+                mib(total),
+                downloaded * 100 / total,
+                remaining.ceil()
+            )?;
+        } else {
+            write!(out, "\rDownloading {label}: {:.1} MiB", mib(downloaded))?;
+        }
+        out.flush()?;
+    }
+    if last_printed > 0 {
+        writeln!(out)?;
+    }
+
+    Ok(body)
+}
+
+/// Temporary path a backup given as a URL is downloaded to,
+/// named after the last segment of the URL; the directory is not created
+fn remote_input_path(url: &str) -> io::Result<PathBuf> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Only http(s) URLs can be used as input, got '{url}'"),
+        ));
+    }
+    let name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|url| url.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("backup");
+    Ok(std::env::temp_dir()
+        .join(format!("nekotatsu-{}", std::process::id()))
+        .join(name))
+}
+
+/// State of a file kept in the app's data directory
+#[derive(Debug)]
+pub struct DataFileStatus {
+    pub name: &'static str,
+    pub path: PathBuf,
+    /// Size in bytes and last modified time, if the file exists
+    pub metadata: Option<(u64, SystemTime)>,
+}
+
+impl std::fmt::Display for DataFileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.metadata {
+            Some((size, modified)) => write!(
+                f,
+                "{}: {:.1} KiB, updated {}",
+                self.name,
+                size as f64 / 1024.0,
+                format_age(modified)
+            ),
+            None => write!(f, "{}: missing", self.name),
+        }
+    }
+}
+
+/// Directory of the data files, relocated with [`DATA_DIR_ENV`]
+pub fn data_dir() -> &'static Path {
+    DATA_DIR.as_path()
+}
+
+/// Checks the files downloaded/generated by `update`
+pub fn data_file_status() -> Vec<DataFileStatus> {
+    [
+        ("tachi_sources.json", DEFAULT_TACHI_SOURCE_PATH.as_path()),
+        ("kotatsu_parsers.json", DEFAULT_KOTATSU_PARSE_PATH.as_path()),
+        ("kotatsu-parsers.zip", DEFAULT_KOTATSU_REPO_PATH.as_path()),
+    ]
+    .into_iter()
+    .map(|(name, path)| DataFileStatus {
+        name,
+        path: path.to_path_buf(),
+        metadata: std::fs::metadata(path)
+            .and_then(|meta| Ok((meta.len(), meta.modified()?)))
+            .ok(),
+    })
+    .collect()
+}
+
+/// Whitelist/blacklist entries matching no source of the extension list, which filter nothing
+pub fn unmatched_filter_entries(
+    entries: &[config::SourceFilterEntry],
+) -> io::Result<Vec<config::SourceFilterEntry>> {
+    let extensions = extensions::ExtensionList::try_from_file(std::fs::File::open(
+        DEFAULT_TACHI_SOURCE_PATH.as_path(),
+    )?)?;
+    Ok(entries
+        .iter()
+        .filter(|entry| {
+            let entry = vec![(*entry).clone()];
+            !extensions
+                .sources()
+                .any(|source| entry.check_source(false, source))
+        })
+        .cloned()
+        .collect())
+}
+
+/// Data files updated further apart than this are likely out of sync
+const DATA_FILE_MAX_AGE_GAP: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Warns if the Tachiyomi extension list and Kotatsu parser list were updated far apart,
+/// since a stale list is a common reason sources fail to match
+fn data_files_age_warning() -> Option<String> {
+    let modified = |path: &std::path::Path| std::fs::metadata(path).and_then(|m| m.modified());
+    let sources = modified(&DEFAULT_TACHI_SOURCE_PATH).ok()?;
+    let parsers = modified(&DEFAULT_KOTATSU_PARSE_PATH).ok()?;
+    let (older, newer, gap) = match parsers.duration_since(sources) {
+        Ok(gap) => ("tachi_sources.json", "kotatsu_parsers.json", gap),
+        Err(e) => ("kotatsu_parsers.json", "tachi_sources.json", e.duration()),
+    };
+
+    (gap > DATA_FILE_MAX_AGE_GAP).then(|| {
+        format!(
+            "{older} is {} days older than {newer}; if sources fail to match, try running `nekotatsu update --force-download`",
+            gap.as_secs() / (24 * 60 * 60)
+        )
+    })
+}
+
+/// Loads the data files and reports anything likely to make conversion fail
+pub fn doctor_report() -> Vec<String> {
+    let mut report = Vec::new();
+    // The parser repo archive is only needed to regenerate `kotatsu_parsers.json`
+    for file in data_file_status()
+        .iter()
+        .filter(|f| f.metadata.is_none() && f.name.ends_with(".json"))
+    {
+        report.push(format!(
+            "[PROBLEM] {} is missing, run `nekotatsu update`",
+            file.name
+        ));
+    }
+    report.extend(data_files_age_warning().map(|warning| format!("[WARNING] {warning}")));
+
+    let parsers = std::fs::read_to_string(DEFAULT_KOTATSU_PARSE_PATH.as_path())
+        .map_err(|e| e.to_string())
+        .and_then(|s| kotatsu::parse_parser_list(&s).map_err(|e| e.to_string()));
+    match &parsers {
+        Ok((parsers, skipped)) => {
+            if *skipped > 0 {
+                report.push(format!(
+                    "[PROBLEM] {skipped} entries in kotatsu_parsers.json could not be read, run `nekotatsu update --force-download`"
+                ));
+            }
+            let without_domains = parsers.iter().filter(|p| p.domains.is_empty()).count();
+            report.push(format!(
+                "Kotatsu parsers: {} ({without_domains} without domains)",
+                parsers.len()
+            ));
+            let mut seen = std::collections::HashSet::new();
+            let mut duplicates = parsers
+                .iter()
+                .filter(|p| !seen.insert(p.name.as_str()))
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>();
+            duplicates.sort();
+            duplicates.dedup();
+            if !duplicates.is_empty() {
+                report.push(format!(
+                    "[PROBLEM] Duplicate parser names: {}",
+                    duplicates.join(", ")
+                ));
+            }
+        }
+        Err(e) => report.push(format!(
+            "[PROBLEM] Unable to read kotatsu_parsers.json: {e}"
+        )),
+    }
+
+    let converter = std::fs::File::open(DEFAULT_KOTATSU_PARSE_PATH.as_path()).and_then(|p| {
+        MangaConverter::try_from_files(p, std::fs::File::open(DEFAULT_TACHI_SOURCE_PATH.as_path())?)
+    });
+    match converter {
+        Ok(mut converter) => {
+            if converter.extensions.skipped() > 0 {
+                report.push(format!(
+                    "[PROBLEM] {} entries in tachi_sources.json could not be read, run `nekotatsu update --force-download`",
+                    converter.extensions.skipped()
+                ));
+            }
+            let ids = converter
+                .extensions
+                .sources()
+                .filter_map(|source| source.id.parse::<i64>().ok())
+                .collect::<Vec<_>>();
+            let unmatched = ids
+                .iter()
+                .filter(|id| {
+                    let manga = nekotatsu::neko::BackupManga {
+                        source: **id,
+                        ..Default::default()
+                    };
+                    converter.get_source_name(&manga) == "UNKNOWN"
+                })
+                .count();
+            report.push(format!(
+                "Tachiyomi sources: {} ({unmatched} without a matching Kotatsu parser)",
+                ids.len()
+            ));
+        }
+        Err(e) if parsers.is_ok() => {
+            report.push(format!("[PROBLEM] Unable to read tachi_sources.json: {e}"))
+        }
+        Err(_) => (),
+    }
+
+    report
+}
+
+/// Formats how long ago `time` was, i.e. `3 days ago`
+pub fn format_age(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (amount, unit) = match seconds {
+        0..60 => return String::from("just now"),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!("{amount} {unit}{} ago", if amount == 1 { "" } else { "s" })
+}
+
+/// Parses `old=new` for `--rename-category`
+fn parse_category_rename(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.trim().is_empty() && !new.trim().is_empty() => {
+            Ok((old.trim().to_string(), new.trim().to_string()))
+        }
+        _ => Err(format!("'{s}' is not in the form old=new")),
+    }
+}
+
+/// Parses `source=url` for `--chapter-url-base`
+fn parse_url_base(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((source, url)) if !source.trim().is_empty() && url.trim().contains("://") => {
+            Ok((source.trim().to_string(), url.trim().to_string()))
+        }
+        _ => Err(format!(
+            "'{s}' is not in the form source=url, i.e. `Asura Scans=https://asuracomic.net`"
+        )),
+    }
+}
+
+/// Parses `source=category` for `--favorites-category`
+fn parse_source_category(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((source, category)) if !source.trim().is_empty() && !category.trim().is_empty() => {
+            Ok((source.trim().to_string(), category.trim().to_string()))
+        }
+        _ => Err(format!("'{s}' is not in the form source=category")),
+    }
+}
+
+/// Parses either a `YYYY-MM-DD` date (UTC midnight) or unix milliseconds
+fn parse_timestamp(s: &str) -> Result<i64, String> {
+    if let Ok(millis) = s.parse::<i64>() {
+        return Ok(millis);
+    }
+
+    let parts = s
+        .splitn(3, '-')
+        .map(|part| part.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("'{s}' is not a date (YYYY-MM-DD) or unix timestamp (ms)"))?;
+    let [year, month, day] = parts[..] else {
+        return Err(format!(
+            "'{s}' is not a date (YYYY-MM-DD) or unix timestamp (ms)"
+        ));
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(format!("'{s}' is not a valid date"));
+    }
+
+    // Days since unix epoch for a proleptic Gregorian date
+    // see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    Ok(days * 24 * 60 * 60 * 1000)
+}
+
+/// Conversion settings resolved from [`Commands::Convert`]
+struct ConvertOptions {
     verbosity: CommandVerbosity,
     favorites_name: String,
-    soft_match: bool,
+    soft_match: Option<SoftMatchMode>,
     print_output: bool,
     config: config::ConfigFile,
+    since: Option<i64>,
+    fail_fast: bool,
+    cancel_flag: Option<Arc<AtomicBool>>,
+    interactive: bool,
+    categories: Vec<String>,
+    excluded_categories: Vec<String>,
+    category_renames: Vec<(String, String)>,
+    list_converted: bool,
+    strip_read: bool,
+    tags_from: TagSource,
+    max_bookmarks: Option<usize>,
+    unmatched_out: Option<PathBuf>,
+    report_missing_parsers: Option<PathBuf>,
+    issue_template: Option<PathBuf>,
+    merge_into: Option<PathBuf>,
+    target_version: Option<u64>,
+    parsers_path: Option<PathBuf>,
+    sources_path: Option<PathBuf>,
+    lang: Option<String>,
+    flatten_categories: bool,
+    include_unmatched: bool,
+    keep_local: bool,
+    unknown_state: Option<String>,
+}
+
+/// Reads a neko backup, or an Aidoku JSON backup converted into the same shape
+fn read_input_backup(
+    input_path: &str,
+    extensions: &extensions::ExtensionList,
+    logger: &mut dyn Logger,
+) -> std::io::Result<nekotatsu::neko::Backup> {
+    let contents = match input_path {
+        "-" => {
+            let mut contents = Vec::new();
+            io::Read::read_to_end(&mut io::stdin().lock(), &mut contents)?;
+            contents
+        }
+        path => std::fs::read(path)?,
+    };
+    if contents.starts_with(b"bplist") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Aidoku backups stored as binary property lists are not supported, export the backup as JSON instead",
+        ));
+    }
+    if contents.trim_ascii_start().first() != Some(&b'{') {
+        return BackupReader::read(contents.as_slice());
+    }
+
+    let (backup, unresolved) =
+        aidoku::decode_aidoku_backup(contents.as_slice())?.to_neko_backup(extensions);
+    logger.log_info("Reading input as an Aidoku backup");
+    for source in unresolved {
+        logger.log_warning(&format!(
+            "No Tachiyomi source found for Aidoku source '{source}', its manga will be skipped"
+        ));
+    }
+    Ok(backup)
+}
+
+/// Drops everything but warnings for `--quiet`
+struct QuietLogger(Box<dyn Logger>);
+
+impl Logger for QuietLogger {
+    fn log_warning(&mut self, message: &str) {
+        self.0.log_warning(message);
+    }
+
+    fn capture_output(&mut self) -> String {
+        self.0.capture_output()
+    }
+}
+
+fn neko_to_kotatsu_command(
+    input_paths: Vec<String>,
+    output_path: PathBuf,
+    options: ConvertOptions,
 ) -> std::io::Result<CommandResult> {
-    let mut logger: Box<dyn Logger> = if print_output {
+    let ConvertOptions {
+        verbosity,
+        favorites_name,
+        soft_match,
+        print_output,
+        config,
+        since,
+        fail_fast,
+        cancel_flag,
+        interactive,
+        categories,
+        excluded_categories,
+        category_renames,
+        list_converted,
+        strip_read,
+        tags_from,
+        max_bookmarks,
+        unmatched_out,
+        report_missing_parsers,
+        issue_template,
+        merge_into,
+        target_version,
+        parsers_path,
+        sources_path,
+        lang,
+        flatten_categories,
+        include_unmatched,
+        keep_local,
+        unknown_state,
+    } = options;
+    // Output is reserved for the backup itself when piping
+    let to_stdout = output_path.as_os_str() == "-";
+    let mut logger: Box<dyn Logger> = if to_stdout {
+        Box::new(std::io::stderr())
+    } else if print_output {
         Box::new(std::io::stdout())
     } else {
         Box::new(Vec::new())
     };
+    if let CommandVerbosity::Quiet = verbosity {
+        logger = Box::new(QuietLogger(logger));
+    }
+
+    // Custom lists are usually deliberately pinned, so their age says nothing
+    if parsers_path.is_none() && sources_path.is_none() {
+        if let Some(warning) = data_files_age_warning() {
+            logger.log_warning(&warning);
+        }
+    }
 
-    let converter = MangaConverter::try_from_files(
-        std::fs::File::open(&DEFAULT_KOTATSU_PARSE_PATH.as_path())?,
-        std::fs::File::open(&DEFAULT_TACHI_SOURCE_PATH.as_path())?,
+    let mut converter = MangaConverter::try_from_files(
+        std::fs::File::open(
+            parsers_path
+                .as_deref()
+                .unwrap_or(DEFAULT_KOTATSU_PARSE_PATH.as_path()),
+        )?,
+        std::fs::File::open(
+            sources_path
+                .as_deref()
+                .unwrap_or(DEFAULT_TACHI_SOURCE_PATH.as_path()),
+        )?,
     )?
-    .with_soft_match(soft_match);
+    .with_soft_match(soft_match == Some(SoftMatchMode::On))
+    .with_soft_match_confirm(soft_match == Some(SoftMatchMode::Confirm))
+    .with_fail_fast(fail_fast)
+    .with_preferred_locale(lang)
+    .with_flatten_categories(flatten_categories)
+    .with_excluded_categories(excluded_categories)
+    .with_category_renames(category_renames)
+    .with_convert_tags(tags_from == TagSource::Genre)
+    .with_max_bookmarks(max_bookmarks)
+    .with_builtin_sources(config.builtin_sources.unwrap_or_default())
+    .with_overrides(config.source_overrides())
+    .with_url_bases(config.source_url_bases())
+    .with_source_categories(config.source_categories())
+    .with_thumbnails(config.thumbnails.clone().unwrap_or_default())
+    .with_include_unmatched(include_unmatched)
+    .with_keep_local(keep_local)
+    .with_unknown_state(unknown_state.unwrap_or_default().to_uppercase());
+    if let Some(flag) = cancel_flag {
+        converter = converter.with_cancel_flag(flag);
+    }
+    if converter.skipped_parsers() > 0 {
+        logger.log_warning(&format!(
+            "Skipped {} Kotatsu parsers that could not be read; if their sources fail to match, try running `nekotatsu update --force-download`",
+            converter.skipped_parsers()
+        ));
+    }
+    if converter.extensions.skipped() > 0 {
+        logger.log_warning(&format!(
+            "Skipped {} extensions in the extension index that could not be read; if their sources fail to match, try running `nekotatsu update --force-download`",
+            converter.extensions.skipped()
+        ));
+    }
 
-    let backup = decode_neko_backup(std::fs::File::open(&input_path)?)?;
+    for (source, parser) in converter.ignored_overrides() {
+        logger.log_warning(&format!(
+            "Override for source '{source}' ignored, Kotatsu parser '{parser}' does not exist"
+        ));
+    }
+
+    let backups = input_paths
+        .iter()
+        .map(|path| read_input_backup(path, &converter.extensions, logger.as_mut()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    if interactive {
+        // Keep prompts out of the converted backup when piping
+        let mut prompt: Box<dyn Write> = if to_stdout {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        };
+        for backup in backups.iter() {
+            prompt_source_overrides(&mut converter, backup, prompt.as_mut())?;
+        }
+    }
 
-    let mut filter_method: Box<dyn FnMut(&extensions::SourceInfo) -> bool> =
+    let mut source_filter: Box<dyn FnMut(&extensions::SourceInfo) -> bool> =
         match (&config.whitelist, &config.blacklist) {
             // Technically whitelist and blacklist should be mutually exclusive,
             // but considering the size of this commit I'm leaving it for now
@@ -146,47 +1086,137 @@ fn neko_to_kotatsu_command(
             (None, Some(blacklist)) => Box::new(|source| blacklist.check_source(true, &source)),
             (_, _) => Box::new(|_| true),
         };
+    let backup_categories = || {
+        backups
+            .iter()
+            .flat_map(|backup| backup.backup_categories.iter())
+    };
+    if let Some(name) = categories
+        .iter()
+        .find(|name| !backup_categories().any(|c| c.name.eq_ignore_ascii_case(name)))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Category '{name}' not found in backup; available categories: {}",
+                backup_categories()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ));
+    }
 
-    let result = converter.convert_backup(
-        backup,
-        &favorites_name,
-        logger.as_mut(),
-        filter_method.as_mut(),
-    );
+    // Languages are only known through the extension list, which the conversion consumes
+    let source_langs: HashMap<String, String> = match issue_template {
+        Some(_) => backups
+            .iter()
+            .flat_map(|backup| backup.backup_manga.iter())
+            .filter_map(|manga| converter.extensions.get_source(manga.source))
+            .map(|source| (source.name, source.lang))
+            .collect(),
+        None => HashMap::new(),
+    };
 
-    let to_make = std::fs::File::create(output_path.clone())?;
-    let options = zip::write::FileOptions::default();
-    let mut writer = zip::ZipWriter::new(to_make);
-    for (name, entry) in [
-        ("history", serde_json::to_string_pretty(&result.history)),
-        (
-            "categories",
-            serde_json::to_string_pretty(&result.categories),
-        ),
-        (
-            "favourites",
-            serde_json::to_string_pretty(&result.favourites),
-        ),
-        ("bookmarks", serde_json::to_string_pretty(&result.bookmarks)),
-        (
-            "index",
-            serde_json::to_string_pretty(&[kotatsu::KotatsuIndexEntry::generate()]),
-        ),
-    ] {
-        match entry {
-            Ok(json) if json.trim() != "[]" => {
-                writer.start_file(name, options)?;
-                writer.write_all(json.as_bytes())?;
-            }
-            Ok(_) => logger.log_info(&format!("{name} is empty, ommitted from converted backup")),
-            Err(e) => logger.log_info(&format!(
-                "[WARNING] Error occurred processing {name}, ommitted from converted backup, original error: {e}"
-            )),
+    let mut result: Option<MangaConversionResult> = None;
+    for backup in backups {
+        let category_references = category_references(&backup.backup_categories);
+        let category_ids = categories
+            .iter()
+            .filter_map(|name| {
+                backup
+                    .backup_categories
+                    .iter()
+                    .position(|c| c.name.eq_ignore_ascii_case(name))
+                    .map(|position| category_references[position])
+            })
+            .collect::<Vec<_>>();
+        let mut filter_method =
+            |source: &extensions::SourceInfo, manga: &nekotatsu::neko::BackupManga| {
+                since.is_none_or(|since| normalize_timestamp(manga.date_added) >= since)
+                    && (categories.is_empty()
+                        || manga.categories.iter().any(|id| category_ids.contains(id)))
+                    && !(strip_read
+                        && !manga.chapters.is_empty()
+                        && manga.chapters.iter().all(|chapter| chapter.read))
+                    && source_filter(source)
+            };
+
+        let converted = converter
+            .clone()
+            .convert_backup(backup, &favorites_name, logger.as_mut(), &mut filter_method)
+            .map_err(|e| io::Error::other(format!("Conversion aborted: {e}")))?;
+        match result.as_mut() {
+            Some(result) => result.merge(converted, max_bookmarks),
+            None => result = Some(converted),
         }
     }
+    let result = result.expect("at least one backup is converted");
 
-    writer.finish()?;
+    let mut writer = BackupWriter::new(&result)
+        .with_app_version(target_version.unwrap_or(kotatsu::DEFAULT_APP_VERSION));
+    if let Some(existing) = &merge_into {
+        writer = writer.merge_into(std::fs::File::open(existing)?)?;
+        logger.log_info(&format!("Merging into {}", existing.display()));
+    }
+    if to_stdout {
+        let buffer = writer.to_bytes(logger.as_mut())?;
+        io::stdout().write_all(&buffer)?;
+        io::stdout().flush()?;
+    } else {
+        writer.write_file(&output_path, logger.as_mut())?;
+    }
 
+    if list_converted {
+        logger.log_info("Converted manga:");
+        for entry in result.history.iter() {
+            logger.log_info(&format!(
+                "{} -> {} ({})",
+                entry.manga.title, entry.manga.source, entry.manga.public_url
+            ));
+        }
+    }
+    if result.included_unmatched > 0 {
+        logger.log_info(&format!(
+            "{} manga from sources without a Kotatsu parser were included with their original URL; they will show up under an unknown source in Kotatsu",
+            result.included_unmatched
+        ));
+    }
+    if result.local_placeholders > 0 {
+        logger.log_warning(&format!(
+            "{} local manga were kept as placeholders and need manual attention: import their files into Kotatsu's local storage",
+            result.local_placeholders
+        ));
+    }
+    if result.excluded_manga > 0 {
+        logger.log_info(&format!(
+            "{} manga in excluded categories were left out",
+            result.excluded_manga
+        ));
+    }
+    if result.tracked_manga > 0 {
+        logger.log_info(&format!(
+            "{} manga have tracking data, which can't be converted; link them to your trackers again in Kotatsu",
+            result.tracked_manga
+        ));
+    }
+    if result.merged_duplicates > 0 {
+        logger.log_info(&format!(
+            "{} duplicate manga entries were merged",
+            result.merged_duplicates
+        ));
+    }
+    logger.log_info(&format!(
+        "Written: {} favourites, {} history entries, {} bookmarks in {} manga",
+        result.favourites.len(),
+        result.history.len(),
+        result
+            .bookmarks
+            .iter()
+            .map(|entry| entry.bookmarks.len())
+            .sum::<usize>(),
+        result.bookmarks.len()
+    ));
     if result.errored_manga == 0 {
         logger.log_info(&format!(
             "{} manga successfully converted ({} ignored), output: {}",
@@ -195,15 +1225,25 @@ fn neko_to_kotatsu_command(
             output_path.display()
         ));
     } else {
-        logger.log_info(&format!(
-            "{} of {} manga and {} sources failed to convert ({} unknown).",
+        let unknown_name = extensions::SourceInfo::default().name;
+        logger.log_warning(&format!(
+            "{} of {} manga failed to convert: {} sources without a Kotatsu parser, {} source IDs not in the extension index.",
             result.errored_manga,
             result.total_manga,
-            result.errored_sources.len(),
+            result
+                .errored_sources
+                .keys()
+                .filter(|name| **name != unknown_name)
+                .count(),
             result.unknown_sources.len()
         ));
+        if !result.unknown_sources.is_empty() {
+            logger.log_warning(
+                "Source IDs missing from the extension index (tachi_sources.json) can't be matched at all; it may be out of date or from another repository than your extensions, try `nekotatsu update --force-download` (with `-t <index URL>` for other repositories)",
+            );
+        }
         match verbosity {
-            CommandVerbosity::None => {
+            CommandVerbosity::Quiet | CommandVerbosity::None => {
                 logger.log_info("Try running again with verbose (-v) on for details");
             }
             CommandVerbosity::Verbose => logger.log_verbose(&format!(
@@ -211,25 +1251,44 @@ fn neko_to_kotatsu_command(
                 result
                     .errored_sources
                     .keys()
-                    .map(|s| s.as_str())
+                    .filter(|name| **name != unknown_name)
+                    .map(|name| match result.parser_suggestions.get(name) {
+                        Some(suggestions) if !suggestions.is_empty() => {
+                            format!("{name} (closest parsers: {})", suggestions.join(", "))
+                        }
+                        _ => name.clone(),
+                    })
                     .collect::<Vec<_>>()
                     .join(", ")
             )),
             CommandVerbosity::VeryVerbose => {
                 logger.log_very_verbose("Sources that errorred:");
                 for (name, url) in result.errored_sources.iter() {
-                    logger.log_very_verbose(&format!(
+                    let mut line = format!(
                         "{name} ({url}), count: {}",
                         result.errored_sources_count.get(name).unwrap_or(&0)
-                    ));
+                    );
+                    if let Some(suggestions) = result.parser_suggestions.get(name) {
+                        line.push_str(&format!(", closest parsers: {}", suggestions.join(", ")));
+                    }
+                    logger.log_very_verbose(&line);
                 }
             }
         }
+        let unmatched = unmatched_sources_list(&result);
+        if let CommandVerbosity::VeryVerbose = verbosity {
+            logger.log_very_verbose("Unmatched sources (name and URL, tab separated):");
+            logger.log_very_verbose(&unmatched);
+        }
+        if let Some(path) = &unmatched_out {
+            std::fs::write(path, format!("{unmatched}\n"))?;
+            logger.log_info(&format!("Unmatched sources written to {}", path.display()));
+        }
         if result.unknown_sources.len() > 0 {
             match verbosity {
-                CommandVerbosity::None => (),
+                CommandVerbosity::Quiet | CommandVerbosity::None => (),
                 CommandVerbosity::Verbose => logger.log_verbose(&format!(
-                    "Unknown Tachiyomi/Mihon source IDs: {}",
+                    "Source IDs not in the extension index: {}",
                     result
                         .unknown_sources
                         .iter()
@@ -238,7 +1297,7 @@ fn neko_to_kotatsu_command(
                         .join(", ")
                 )),
                 CommandVerbosity::VeryVerbose => {
-                    logger.log_very_verbose("Unknown Tachiyomi/Mihon source IDs:");
+                    logger.log_very_verbose("Source IDs not in the extension index:");
                     for id in result.unknown_sources.iter() {
                         logger.log_very_verbose(id);
                     }
@@ -256,10 +1315,60 @@ fn neko_to_kotatsu_command(
         }
     }
 
-    if soft_match {
+    if let Some(path) = &report_missing_parsers {
+        let report = serde_json::to_string_pretty(&missing_parsers_report(&result))?;
+        std::fs::write(path, format!("{report}\n"))?;
+        logger.log_info(&format!(
+            "Missing parser report written to {}",
+            path.display()
+        ));
+    }
+
+    if let Some(path) = &issue_template {
+        let issues = issue_templates(&missing_parsers_report(&result), &source_langs);
+        if path.as_os_str() == "-" {
+            // stdout already carries the backup when it is also the output
+            if to_stdout {
+                eprint!("{issues}");
+            } else {
+                print!("{issues}");
+            }
+        } else {
+            std::fs::write(path, &issues)?;
+            logger.log_info(&format!("Issue templates written to {}", path.display()));
+        }
+    }
+
+    if !result.soft_match_candidates.is_empty() {
+        logger.log_info(
+            "Candidate matches found by soft match, not converted; add the ones you trust to the `overrides` table of your config file and convert again:",
+        );
+        for (source, parser) in result.soft_match_candidates.iter() {
+            logger.log_info(&format!("{source:?} = {parser:?}"));
+        }
+    }
+    if soft_match == Some(SoftMatchMode::On) {
         logger.log_info(
             "[IMPORTANT] Command run with 'soft match' on; some sources may not behave as intended",
-        )
+        );
+        if !result.soft_matched_sources.is_empty() {
+            logger.log_info(&format!(
+                "Sources matched only by soft match (double-check these): {}",
+                result.soft_matched_sources.join(", ")
+            ));
+        }
+    }
+
+    if let (CommandVerbosity::Quiet, true, false) = (&verbosity, print_output, to_stdout) {
+        println!("{}", output_path.display());
+    }
+
+    if result.errored_manga > 0 {
+        return Ok(CommandResult::Incomplete {
+            path: output_path.display().to_string(),
+            output: logger.capture_output(),
+            errored: result.errored_manga,
+        });
     }
 
     Ok(CommandResult::Success(
@@ -268,16 +1377,351 @@ fn neko_to_kotatsu_command(
     ))
 }
 
+/// Sources that failed to convert in a format that can be pasted into parser requests;
+/// unknown sources only have their ID
+fn unmatched_sources_list(result: &MangaConversionResult) -> String {
+    let mut lines = result
+        .errored_sources
+        .iter()
+        .filter(|(name, _)| **name != extensions::SourceInfo::default().name)
+        .map(|(name, url)| format!("{name}\t{url}"))
+        .collect::<Vec<_>>();
+    lines.sort();
+    let mut unknown = result
+        .unknown_sources
+        .iter()
+        .map(|id| format!("Unknown source {id}\t"))
+        .collect::<Vec<_>>();
+    unknown.sort();
+    lines.extend(unknown);
+
+    lines.join("\n")
+}
+
+#[derive(serde::Serialize)]
+struct MissingParser {
+    name: String,
+    base_url: String,
+    manga_count: usize,
+    /// Closest Kotatsu parsers, in case one of them is the right one
+    suggestions: Vec<String>,
+}
+
+/// Sources that failed to convert, grouped by where the fix has to happen
+#[derive(serde::Serialize)]
+struct MissingParsersReport {
+    /// Known Tachiyomi/Mihon sources without a Kotatsu parser; request these from kotatsu-parsers
+    no_kotatsu_parser: Vec<MissingParser>,
+    /// Source IDs that are not in `tachi_sources.json`; update it with the repository
+    /// the extensions were installed from (`nekotatsu update -t <index URL>`)
+    not_in_extension_list: Vec<i64>,
+    /// Every manga that failed to convert and why
+    failed_manga: Vec<FailedManga>,
+}
+
+#[derive(serde::Serialize)]
+struct FailedManga {
+    title: String,
+    #[serde(flatten)]
+    error: ConversionErrorKind,
+}
+
+fn missing_parsers_report(result: &MangaConversionResult) -> MissingParsersReport {
+    let mut no_kotatsu_parser = result
+        .errored_sources
+        .iter()
+        .filter(|(name, _)| **name != extensions::SourceInfo::default().name)
+        .map(|(name, url)| MissingParser {
+            name: name.clone(),
+            base_url: url.clone(),
+            manga_count: result.errored_sources_count.get(name).copied().unwrap_or(0),
+            suggestions: result
+                .parser_suggestions
+                .get(name)
+                .cloned()
+                .unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    no_kotatsu_parser.sort_by(|a, b| b.manga_count.cmp(&a.manga_count).then(a.name.cmp(&b.name)));
+    let mut not_in_extension_list = result
+        .unknown_sources
+        .iter()
+        .filter_map(|id| id.parse().ok())
+        .collect::<Vec<_>>();
+    not_in_extension_list.sort();
+
+    MissingParsersReport {
+        no_kotatsu_parser,
+        not_in_extension_list,
+        failed_manga: result
+            .failures
+            .iter()
+            .map(|(title, error)| FailedManga {
+                title: title.clone(),
+                error: error.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// One pre-filled issue per source without a Kotatsu parser, following the fields of
+/// the failing conversion issue form
+fn issue_templates(report: &MissingParsersReport, langs: &HashMap<String, String>) -> String {
+    report
+        .no_kotatsu_parser
+        .iter()
+        .map(|source| {
+            let lang = langs.get(&source.name).map_or("unknown", String::as_str);
+            let suggestions = match source.suggestions.as_slice() {
+                [] => String::new(),
+                suggestions => format!("\nClosest Kotatsu parsers: {}", suggestions.join(", ")),
+            };
+            format!(
+                "## [Failing Conversion]: {name}\n\n\
+                 ### Source Name\n\n{name}\n\n\
+                 ### Kotatsu Link\n\n_No response_\n\n\
+                 ### Extension Implementation\n\n_No response_\n\n\
+                 ### Additional Context\n\n\
+                 Base URL: {url}\nLanguage: {lang}\nManga in backup: {count}{suggestions}\n\n",
+                name = source.name,
+                url = source.base_url,
+                count = source.manga_count,
+            )
+        })
+        .collect()
+}
+
+/// Asks which parser to use for every known source in `backup` that has no matching parser,
+/// applying the choices to `converter` and optionally saving them to a config file
+fn prompt_source_overrides(
+    converter: &mut MangaConverter,
+    backup: &nekotatsu::neko::Backup,
+    prompt: &mut dyn Write,
+) -> std::io::Result<()> {
+    let read_answer = |prompt: &mut dyn Write, question: &str| -> io::Result<String> {
+        write!(prompt, "{question}")?;
+        prompt.flush()?;
+        let mut buf = String::new();
+        io::stdin().read_line(&mut buf)?;
+        Ok(buf.trim().to_string())
+    };
+
+    let unmatched = converter
+        .match_sources(backup)
+        .into_iter()
+        .filter(|source| source.id != 0 && source.parser.is_none())
+        .filter_map(|source| converter.extensions.get_source(source.id).zip(Some(source)))
+        .collect::<Vec<_>>();
+    let mut chosen = Vec::new();
+    for (info, source) in unmatched {
+        let candidates = converter
+            .parser_candidates(&info, 5)
+            .into_iter()
+            .map(|p| (p.name.clone(), p.domains.join(", ")))
+            .collect::<Vec<_>>();
+        writeln!(
+            prompt,
+            "No Kotatsu parser found for {} ({}), {} manga:",
+            source.name, source.base_url, source.manga_count
+        )?;
+        for (i, (name, domains)) in candidates.iter().enumerate() {
+            writeln!(prompt, "  {}) {name} [{domains}]", i + 1)?;
+        }
+        loop {
+            let answer = read_answer(
+                prompt,
+                "Pick a number, type a parser name or leave empty to skip: ",
+            )?;
+            let parser = match answer.parse::<usize>() {
+                _ if answer.is_empty() => break,
+                Ok(n) if (1..=candidates.len()).contains(&n) => candidates[n - 1].0.clone(),
+                _ if converter.has_parser(&answer.to_uppercase()) => answer.to_uppercase(),
+                _ => {
+                    writeln!(
+                        prompt,
+                        "'{answer}' is not one of the options or a parser name"
+                    )?;
+                    continue;
+                }
+            };
+            converter.map_source(source.id, &parser);
+            chosen.push((source.id, parser));
+            break;
+        }
+    }
+
+    if chosen.is_empty() {
+        return Ok(());
+    }
+    let path = read_answer(
+        prompt,
+        "Save these choices as overrides in a config file? Enter a path or leave empty to skip: ",
+    )?;
+    if path.is_empty() {
+        return Ok(());
+    }
+    let path = PathBuf::from(path);
+    let mut table: toml::Table = match std::fs::read_to_string(&path) {
+        Ok(s) => s
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+        Err(e) => return Err(e),
+    };
+    let overrides = table
+        .entry("overrides")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        .as_table_mut()
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "`overrides` in config file is not a table",
+        ))?;
+    for (id, parser) in chosen {
+        overrides.insert(id.to_string(), toml::Value::String(parser));
+    }
+    std::fs::write(
+        &path,
+        toml::to_string(&table).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+    )?;
+    writeln!(prompt, "Saved overrides to {}", path.display())?;
+
+    Ok(())
+}
+
+/// Resolves the sources in a backup using the downloaded source and parser lists
+pub fn list_sources(input_path: &str, soft_match: bool) -> std::io::Result<Vec<SourceMatch>> {
+    let mut converter = MangaConverter::try_from_files(
+        std::fs::File::open(DEFAULT_KOTATSU_PARSE_PATH.as_path())?,
+        std::fs::File::open(DEFAULT_TACHI_SOURCE_PATH.as_path())?,
+    )?
+    .with_soft_match(soft_match);
+    let backup = BackupReader::read_file(input_path)?;
+
+    Ok(converter.match_sources(&backup))
+}
+
+#[derive(serde::Serialize)]
+struct MatchMapEntry {
+    name: String,
+    parser: Option<String>,
+    method: Option<MatchMethod>,
+}
+
+/// The `match-map` JSON for a backup, overrides from `config_file` included
+pub fn match_map(
+    input_path: &str,
+    soft_match: bool,
+    config_file: Option<&Path>,
+) -> std::io::Result<String> {
+    let mut converter = MangaConverter::try_from_files(
+        std::fs::File::open(DEFAULT_KOTATSU_PARSE_PATH.as_path())?,
+        std::fs::File::open(DEFAULT_TACHI_SOURCE_PATH.as_path())?,
+    )?
+    .with_soft_match(soft_match);
+    if let Some(path) = config_file {
+        let config: config::ConfigFile = toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        converter = converter
+            .with_builtin_sources(config.builtin_sources.unwrap_or_default())
+            .with_overrides(config.source_overrides());
+        for (source, parser) in converter.ignored_overrides() {
+            eprintln!("[WARNING] Override for '{source}' -> '{parser}' ignored");
+        }
+    }
+    let backup = BackupReader::read_file(input_path)?;
+
+    let map: std::collections::BTreeMap<i64, MatchMapEntry> = converter
+        .match_sources(&backup)
+        .into_iter()
+        .map(|source| {
+            let entry = MatchMapEntry {
+                name: source.name,
+                parser: source.parser,
+                method: source.method,
+            };
+            (source.id, entry)
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&map)?)
+}
+
+#[derive(serde::Serialize)]
+struct ExportEntry {
+    title: String,
+    source: String,
+    author: String,
+    status: &'static str,
+    url: String,
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The `export` listing of the manga in a backup
+pub fn export_library(input_path: &str, format: ExportFormat) -> std::io::Result<String> {
+    let mut converter = MangaConverter::try_from_files(
+        std::fs::File::open(DEFAULT_KOTATSU_PARSE_PATH.as_path())?,
+        std::fs::File::open(DEFAULT_TACHI_SOURCE_PATH.as_path())?,
+    )?;
+    let backup = read_input_backup(input_path, &converter.extensions, &mut io::stderr())?;
+
+    let entries: Vec<ExportEntry> = backup
+        .backup_manga
+        .iter()
+        .map(|manga| {
+            let source = converter.extensions.get_source(manga.source);
+            // Sources without a parser still get a link to the source's own site
+            let url = match (converter.convert_manga(manga), &source) {
+                (Ok(converted), _) => converted.public_url,
+                (Err(_), Some(source)) => format!("{}{}", source.baseUrl, manga.url),
+                (Err(_), None) => manga.url.clone(),
+            };
+            ExportEntry {
+                title: manga.title.clone(),
+                source: match (manga.source, source) {
+                    (0, _) => String::from("Local"),
+                    (_, Some(source)) => source.name,
+                    (id, None) => format!("Unknown ({id})"),
+                },
+                author: manga.author.clone(),
+                status: kotatsu_state(manga.status),
+                url,
+            }
+        })
+        .collect();
+
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&entries)?),
+        ExportFormat::Csv => {
+            let mut lines = vec![String::from("title,source,author,status,url")];
+            lines.extend(entries.iter().map(|e| {
+                [e.title.as_str(), &e.source, &e.author, e.status, &e.url]
+                    .map(csv_field)
+                    .join(",")
+            }));
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
 fn kotatsu_to_neko_manga(k: &KotatsuMangaBackup) -> nekotatsu::neko::BackupManga {
     nekotatsu::neko::BackupManga {
-        source: 2499283573021220255, // Not sure if this is a volatile value
+        source: source_ids::MANGADEX,
         url: k.public_url.clone(),
         title: k.title.clone(),
+        custom_title: k.alt_title.clone().unwrap_or_default(),
         artist: k.author.clone(), // Kotatsu doesn't differentiate
         author: k.author.clone(),
         status: match k.state.as_str() {
             "ONGOING" => 1,
             "FINISHED" => 2,
+            "RESTRICTED" => 3,
             "ABANDONED" => 5,
             "PAUSED" => 6,
             _ => 0,
@@ -287,25 +1731,74 @@ fn kotatsu_to_neko_manga(k: &KotatsuMangaBackup) -> nekotatsu::neko::BackupManga
             .strip_suffix(".256.jpg")
             .map(str::to_string)
             .unwrap_or(k.cover_url.clone()),
-
+        genre: k.tags.iter().map(|tag| tag.title.clone()).collect(),
         ..Default::default()
     }
 }
 
-fn kotatsu_to_neko(input_path: String, output_path: PathBuf) -> std::io::Result<CommandResult> {
+/// Upper bound on chapters synthesized for a single history entry
+const MAX_SYNTHESIZED_CHAPTERS: i32 = 500;
+
+/// Creates placeholder chapters marked as read up to the position stored in a Kotatsu history entry.
+///
+/// Kotatsu only stores a hashed chapter id and how far through the manga the reader is,
+/// so the actual chapter URLs cannot be recovered. Since the total chapter count is unknown,
+/// the number of read chapters is estimated by inverting the forward conversion
+/// (`percent = (latest - 1) / newest`) under the assumption that the latest read chapter
+/// is also the newest one; treat the result as approximate.
+fn synthesize_read_chapters(
+    history: &KotatsuHistoryBackup,
+    manga_url: &str,
+) -> Vec<nekotatsu::neko::BackupChapter> {
+    if history.chapter_id == 0 {
+        return Vec::new();
+    }
+
+    let read_count = if history.percent > 0.0 && history.percent < 1.0 {
+        ((1.0 / (1.0 - history.percent)).round() as i32).clamp(1, MAX_SYNTHESIZED_CHAPTERS)
+    } else {
+        1
+    };
+
+    (1..=read_count)
+        .map(|number| nekotatsu::neko::BackupChapter {
+            url: format!("{manga_url}#chapter-{number}"),
+            name: format!("Chapter {number}"),
+            read: true,
+            last_page_read: if number == read_count {
+                history.page
+            } else {
+                0
+            },
+            chapter_number: number as f32,
+            source_order: read_count - number,
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn kotatsu_to_neko(
+    input_path: String,
+    output_path: PathBuf,
+    compression: Compression,
+    quiet: bool,
+) -> std::io::Result<CommandResult> {
     // I would at the very least like to be able to get the latest chapter and the bookmarks
     // but the process of getting the URL from the ID is not reasonably reversible as far as I can see
-    println!("Note: limited support. Chapter information (including history and bookmarks) cannot be converted from Kotatsu backups.");
+    if !quiet {
+        println!("Note: limited support. Bookmarks cannot be converted from Kotatsu backups and reading progress is approximated.");
+    }
 
-    let bytes = std::fs::File::open(&input_path)?;
-    let mut reader = zip::read::ZipArchive::new(bytes)?;
+    let mut reader = BackupReader::open_kotatsu(std::fs::File::open(&input_path)?)?;
     let mut history: Option<Vec<KotatsuHistoryBackup>> = None;
     let mut categories: Option<Vec<KotatsuCategoryBackup>> = None;
     let mut favourites: Option<Vec<KotatsuFavouriteBackup>> = None;
     // let mut bookmarks: Option<Vec<KotatsuBookmarkBackup>> = None;
     for i in 0..reader.len() {
         let file = reader.by_index(i)?;
-        println!("File: {}", file.name());
+        if !quiet {
+            println!("File: {}", file.name());
+        }
         match file.name() {
             "history" => history = Some(serde_json::from_reader(file)?),
             "categories" => categories = Some(serde_json::from_reader(file)?),
@@ -320,7 +1813,9 @@ fn kotatsu_to_neko(input_path: String, output_path: PathBuf) -> std::io::Result<
     if let Some(history) = history {
         for entry in history {
             if !neko_manga.contains_key(&entry.manga_id) {
-                neko_manga.insert(entry.manga_id, kotatsu_to_neko_manga(&entry.manga));
+                let mut manga = kotatsu_to_neko_manga(&entry.manga);
+                manga.chapters = synthesize_read_chapters(&entry, &manga.url);
+                neko_manga.insert(entry.manga_id, manga);
             }
         }
     }
@@ -331,7 +1826,8 @@ fn kotatsu_to_neko(input_path: String, output_path: PathBuf) -> std::io::Result<
                     entry.category_id,
                     nekotatsu::neko::BackupCategory {
                         name: entry.title.clone(),
-                        order: entry.sort_key,
+                        // Manga refer to categories by their order
+                        order: entry.category_id as i32,
                         ..Default::default()
                     },
                 );
@@ -350,19 +1846,32 @@ fn kotatsu_to_neko(input_path: String, output_path: PathBuf) -> std::io::Result<
         }
     }
 
-    let backup = nekotatsu::neko::Backup {
+    let mut backup = nekotatsu::neko::Backup {
         backup_manga: neko_manga.into_iter().map(|e| e.1).collect(),
         backup_categories: neko_categories.into_iter().map(|e| e.1).collect(),
     };
+    // Collected from hash maps, so sorted to make the output reproducible
+    backup
+        .backup_manga
+        .sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.url.cmp(&b.url)));
+    backup.backup_categories.sort_by_key(|c| c.order);
+    let orphans = clean_orphan_categories(&mut backup);
+    if orphans > 0 {
+        eprintln!("[WARNING] Dropped {orphans} references to categories missing from the Kotatsu backup; those manga are kept without them");
+    }
     let mut buffer = backup.encode_to_vec();
     let mut output = std::fs::File::create(output_path.clone())?;
-    let mut encoder = GzEncoder::new(&mut output, Compression::fast());
+    let mut encoder = GzEncoder::new(&mut output, compression);
     encoder.write_all(&mut buffer)?;
 
-    println!(
-        "Conversion completed successfully, output: {}",
-        output_path.display()
-    );
+    if quiet {
+        println!("{}", output_path.display());
+    } else {
+        println!(
+            "Conversion completed successfully, output: {}",
+            output_path.display()
+        );
+    }
 
     Ok(CommandResult::Success(
         output_path.display().to_string(),
@@ -370,22 +1879,55 @@ fn kotatsu_to_neko(input_path: String, output_path: PathBuf) -> std::io::Result<
     ))
 }
 
+/// Drops references to categories that aren't in the backup, i.e. favourites of categories
+/// missing from the Kotatsu backup, which would keep the backup from importing cleanly;
+/// returns how many were dropped
+fn clean_orphan_categories(backup: &mut nekotatsu::neko::Backup) -> usize {
+    let orders: HashSet<i32> = backup.backup_categories.iter().map(|c| c.order).collect();
+    let mut dropped = 0;
+    for manga in backup.backup_manga.iter_mut() {
+        let before = manga.categories.len();
+        manga.categories.retain(|order| orders.contains(order));
+        dropped += before - manga.categories.len();
+    }
+    dropped
+}
+
+/// The directory `clear` removes when the data directory isn't relocated
+fn default_data_root() -> io::Result<&'static Path> {
+    #[cfg(not(target_os = "windows"))]
+    return Ok(PROJECT_DIR.data_dir());
+    #[cfg(target_os = "windows")]
+    return PROJECT_DIR.data_dir().parent().ok_or(std::io::Error::new(
+        io::ErrorKind::Other,
+        "Unable to get Nekotatsu data folder path",
+    ));
+}
+
 pub fn run_command(command: Commands) -> std::io::Result<CommandResult> {
     match command {
         Commands::Update {
             kotatsu_link,
             tachi_link,
             force_download,
+            parser_stats,
+            proxy,
+            progress,
         } => {
-            let data_path = PathBuf::from(PROJECT_DIR.data_dir());
+            let data_path = DATA_DIR.clone();
             if !data_path.try_exists()? {
                 std::fs::create_dir_all(&data_path)?;
             }
             let tachi_path = data_path.join("tachi_sources.json");
             if force_download || !tachi_path.try_exists()? {
-                let response = reqwest::blocking::get(tachi_link);
-                if let Ok(response) = response {
-                    let text = response.text().unwrap();
+                let response = download_with_progress(
+                    &tachi_link,
+                    "extension info",
+                    proxy.as_deref(),
+                    progress.as_deref(),
+                    &mut io::stdout(),
+                );
+                if let Ok(text) = response {
                     std::fs::write(tachi_path.as_path(), text)?;
                     println!("Successfully updated extension info.");
                 } else {
@@ -394,13 +1936,16 @@ pub fn run_command(command: Commands) -> std::io::Result<CommandResult> {
                 }
             }
 
-            let kotatsu_path = data_path.join("kotatsu-parsers.zip");
+            let kotatsu_path = DEFAULT_KOTATSU_REPO_PATH.to_path_buf();
             if force_download || !kotatsu_path.try_exists()? {
-                let response = reqwest::blocking::get(kotatsu_link);
-                if let Ok(response) = response {
-                    let b = response
-                        .bytes()
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                let response = download_with_progress(
+                    &kotatsu_link,
+                    "parser repo",
+                    proxy.as_deref(),
+                    progress.as_deref(),
+                    &mut io::stdout(),
+                );
+                if let Ok(b) = response {
                     std::fs::write(kotatsu_path.as_path(), b)?;
                     println!("Successfully downloaded parser repo.");
                 } else {
@@ -412,81 +1957,496 @@ pub fn run_command(command: Commands) -> std::io::Result<CommandResult> {
             let new_data = std::fs::File::open(&kotatsu_path)?;
             let save_to = std::fs::File::create(&DEFAULT_KOTATSU_PARSE_PATH.as_path())?;
 
-            kotatsu::update_parsers(&new_data, &save_to)?;
-            println!("Successfully updated parser info.");
+            let stats = kotatsu::update_parsers(&new_data, &save_to)?;
+            println!(
+                "Successfully updated parser info: {} parsers, {} without domains.",
+                stats.total, stats.without_domains
+            );
+            if !stats.skipped_files.is_empty() {
+                println!(
+                    "[WARNING] Skipped {} parser files that could not be read; their sources won't be matched",
+                    stats.skipped_files.len()
+                );
+            }
+            if parser_stats {
+                println!("Manga: {}", stats.manga);
+                println!("Hentai: {}", stats.hentai);
+                println!("Comics: {}", stats.comics);
+                println!("Other: {}", stats.other);
+            }
 
             Ok(CommandResult::None)
         }
 
         Commands::Convert {
             input,
+            more_inputs,
             output,
+            output_dir,
             favorites_name,
             verbose,
             very_verbose,
+            quiet,
             reverse,
+            input_format,
+            output_format,
+            compression,
             soft_match,
+            lang,
             force,
+            overwrite_policy,
             print_output,
             config_file,
+            parsers,
+            sources,
+            whitelist,
+            blacklist,
+            config,
+            cancel_flag,
+            since,
+            category,
+            exclude_category,
+            rename_category,
+            chapter_url_base,
+            favorites_category,
+            flatten_categories,
+            strip_read,
+            tags_from,
+            max_bookmarks,
+            fail_fast,
+            keep_going: _,
+            interactive,
+            include_unmatched,
+            keep_local,
+            unknown_state,
+            list_converted,
+            unmatched_out,
+            report_missing_parsers,
+            issue_template,
+            merge_into,
+            target_version,
         } => {
-            let conf = match config_file {
-                Some(path) => {
+            let conf = match (config, config_file) {
+                (Some(config), _) => config,
+                (None, Some(path)) => {
                     let s = std::fs::read_to_string(path)?;
                     toml::from_str(&s)
                         .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
                 }
-                None => config::ConfigFile::default(),
+                (None, None) => config::ConfigFile::default(),
+            };
+            let conf = config::ConfigFile {
+                whitelist: Some(whitelist)
+                    .filter(|list| !list.is_empty())
+                    .or(conf.whitelist),
+                blacklist: Some(blacklist)
+                    .filter(|list| !list.is_empty())
+                    .or(conf.blacklist),
+                categories: match favorites_category.is_empty() {
+                    true => conf.categories,
+                    false => Some(
+                        conf.categories
+                            .unwrap_or_default()
+                            .into_iter()
+                            .chain(favorites_category)
+                            .collect(),
+                    ),
+                },
+                url_bases: match chapter_url_base.is_empty() {
+                    true => conf.url_bases,
+                    false => Some(
+                        conf.url_bases
+                            .unwrap_or_default()
+                            .into_iter()
+                            .chain(chapter_url_base)
+                            .collect(),
+                    ),
+                },
+                ..conf
+            };
+            let reverse = match (input_format, output_format) {
+                (Some(input), Some(output)) if input == output => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "--input-format and --output-format must be different",
+                    ));
+                }
+                (Some(BackupFormat::Kotatsu), _) | (_, Some(BackupFormat::Neko)) => true,
+                (Some(BackupFormat::Neko), _) | (_, Some(BackupFormat::Kotatsu)) => false,
+                (None, None) => reverse,
             };
-            let input_path = input;
-            let output_path = output.unwrap_or(if reverse {
-                String::from("kotatsu_converted")
+            let stdin_input = input == "-";
+            if stdin_input && (reverse || interactive) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Reading the backup from stdin can't be combined with --reverse or --interactive",
+                ));
+            }
+            if reverse && output.as_deref() == Some("-") {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Writing the backup to stdout can't be combined with --reverse",
+                ));
+            }
+            if !more_inputs.is_empty() && reverse {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Several backups can only be converted at once when converting to Kotatsu",
+                ));
+            }
+            if more_inputs
+                .iter()
+                .any(|input| input == "-" || input.contains("://"))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Only the first backup can be read from stdin or downloaded, the others must be local files",
+                ));
+            }
+            let remote_input = input.contains("://");
+            let input_path = if remote_input {
+                remote_input_path(&input)?.display().to_string()
             } else {
-                String::from("neko_converted")
-            });
-            let output_path = std::path::Path::new(&output_path)
-                .with_extension("")
-                .with_extension(if reverse { "tachibk" } else { "zip" });
-            if !force && output_path.exists() {
-                print!(
-                    "File with name {} already exists; overwrite? Y(es)/N(o): ",
-                    output_path.display()
-                );
-                io::stdout().flush()?;
-                let mut buf = String::new();
-                io::stdin().read_line(&mut buf)?;
-                match buf.trim_end().to_lowercase().as_str() {
-                    "y" | "yes" => (),
-                    _ => {
-                        println!("Conversion cancelled");
+                input.clone()
+            };
+            let to_stdout = output.as_deref() == Some("-");
+            let extension = if reverse { "tachibk" } else { "zip" };
+            let output_name = match (output, &output_dir) {
+                (Some(output), _) => PathBuf::from(output)
+                    .with_extension("")
+                    .with_extension(extension),
+                (None, Some(_)) if stdin_input => {
+                    PathBuf::from(format!("neko_converted.{extension}"))
+                }
+                (None, Some(_)) => {
+                    PathBuf::from(output_name_for_input(Path::new(&input_path), extension))
+                }
+                (None, None) if reverse => PathBuf::from(format!("kotatsu_converted.{extension}")),
+                (None, None) => PathBuf::from(format!("neko_converted.{extension}")),
+            };
+            let output_path = if to_stdout {
+                PathBuf::from("-")
+            } else {
+                output_dir.unwrap_or_default().join(output_name)
+            };
+            let overwrite_policy = if force {
+                OverwritePolicy::Overwrite
+            } else {
+                overwrite_policy.unwrap_or(OverwritePolicy::Ask)
+            };
+            let output_path = if to_stdout || !output_path.exists() {
+                output_path
+            } else {
+                match overwrite_policy {
+                    OverwritePolicy::Overwrite => output_path,
+                    OverwritePolicy::Skip => {
+                        println!(
+                            "File with name {} already exists, skipping conversion",
+                            output_path.display()
+                        );
                         return Ok(CommandResult::None);
                     }
+                    OverwritePolicy::Rename => {
+                        let renamed = numbered_output_path(&output_path);
+                        println!(
+                            "File with name {} already exists, writing to {} instead",
+                            output_path.display(),
+                            renamed.display()
+                        );
+                        renamed
+                    }
+                    // The answer would be read from the piped backup
+                    OverwritePolicy::Ask if stdin_input => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!(
+                                "File with name {} already exists; use --force or --overwrite-policy when reading from stdin",
+                                output_path.display()
+                            ),
+                        ));
+                    }
+                    OverwritePolicy::Ask => {
+                        print!(
+                            "File with name {} already exists; overwrite? Y(es)/N(o): ",
+                            output_path.display()
+                        );
+                        io::stdout().flush()?;
+                        let mut buf = String::new();
+                        io::stdin().read_line(&mut buf)?;
+                        match buf.trim_end().to_lowercase().as_str() {
+                            "y" | "yes" => output_path,
+                            _ => {
+                                println!("Conversion cancelled");
+                                return Ok(CommandResult::None);
+                            }
+                        }
+                    }
+                }
+            };
+
+            if let Some(dir) = output_path
+                .parent()
+                .filter(|dir| !to_stdout && !dir.as_os_str().is_empty() && !dir.exists())
+            {
+                eprintln!(
+                    "[WARNING] Output directory {} does not exist, creating it",
+                    dir.display()
+                );
+                std::fs::create_dir_all(dir).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("Could not create output directory {}: {e}", dir.display()),
+                    )
+                })?;
+            }
+
+            if remote_input {
+                let mut progress_out: Box<dyn Write> = if to_stdout {
+                    Box::new(io::stderr())
+                } else {
+                    Box::new(io::stdout())
+                };
+                let body =
+                    download_with_progress(&input, "backup", None, None, progress_out.as_mut())?;
+                let path = PathBuf::from(&input_path);
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
                 }
+                std::fs::write(&path, body)?;
+            }
+
+            let expected = if reverse {
+                BackupFormat::Kotatsu
+            } else {
+                BackupFormat::Neko
+            };
+            match BackupFormat::detect(Path::new(&input_path)) {
+                Some(detected) if !stdin_input && detected != expected => eprintln!(
+                    "[WARNING] Input looks like a {} backup but is converted as a {} backup; use --input-format to choose the direction",
+                    detected.name(),
+                    expected.name()
+                ),
+                _ => (),
             }
 
-            if reverse {
-                kotatsu_to_neko(input_path, output_path)
+            let result = if reverse {
+                kotatsu_to_neko(
+                    input_path.clone(),
+                    output_path,
+                    compression.unwrap_or(CompressionLevel::Fast).into(),
+                    quiet,
+                )
             } else {
-                let verbosity = match (very_verbose, verbose) {
-                    (true, _) => CommandVerbosity::VeryVerbose,
-                    (_, true) => CommandVerbosity::Verbose,
+                let verbosity = match (very_verbose, verbose, quiet) {
+                    (true, _, _) => CommandVerbosity::VeryVerbose,
+                    (_, true, _) => CommandVerbosity::Verbose,
+                    (_, _, true) => CommandVerbosity::Quiet,
                     _ => CommandVerbosity::None,
                 };
                 // neko_to_kotatsu(
                 neko_to_kotatsu_command(
-                    input_path,
+                    std::iter::once(input_path.clone())
+                        .chain(more_inputs)
+                        .collect(),
                     output_path,
-                    verbosity,
-                    favorites_name,
-                    soft_match,
-                    print_output,
-                    conf,
+                    ConvertOptions {
+                        verbosity,
+                        favorites_name,
+                        soft_match,
+                        print_output,
+                        config: conf,
+                        since,
+                        fail_fast,
+                        cancel_flag,
+                        interactive,
+                        categories: category,
+                        excluded_categories: exclude_category,
+                        category_renames: rename_category,
+                        list_converted,
+                        strip_read,
+                        tags_from: tags_from.unwrap_or(TagSource::Genre),
+                        max_bookmarks,
+                        unmatched_out,
+                        report_missing_parsers,
+                        issue_template,
+                        merge_into,
+                        target_version,
+                        parsers_path: parsers,
+                        sources_path: sources,
+                        lang,
+                        flatten_categories,
+                        include_unmatched,
+                        keep_local,
+                        unknown_state,
+                    },
                 )
+            };
+
+            if remote_input {
+                let path = PathBuf::from(&input_path);
+                let _ = std::fs::remove_file(&path);
+                if let Some(dir) = path.parent() {
+                    let _ = std::fs::remove_dir(dir);
+                }
+            }
+            result
+        }
+
+        Commands::Info => {
+            println!("Data directory: {}", DATA_DIR.display());
+            for file in data_file_status() {
+                println!("{file}");
+            }
+
+            if let Ok(parsers) = std::fs::read_to_string(DEFAULT_KOTATSU_PARSE_PATH.as_path()) {
+                match kotatsu::parse_parser_list(&parsers) {
+                    Ok((parsers, _)) => println!("Kotatsu parsers: {}", parsers.len()),
+                    Err(e) => println!("[WARNING] Unable to read kotatsu_parsers.json: {e}"),
+                }
+            }
+
+            Ok(CommandResult::None)
+        }
+
+        Commands::ListSources { input, soft_match } => {
+            let matches = list_sources(&input, soft_match)?;
+            for source in matches.iter() {
+                println!(
+                    "{} ({}) -> {} [{} manga]",
+                    source.name,
+                    source.id,
+                    source.parser.as_deref().unwrap_or("none"),
+                    source.manga_count
+                );
             }
+            let matched = matches.iter().filter(|s| s.parser.is_some()).count();
+            println!(
+                "{matched} of {} sources matched a Kotatsu parser",
+                matches.len()
+            );
+
+            Ok(CommandResult::None)
+        }
+
+        Commands::Doctor => {
+            let report = doctor_report();
+            for line in report.iter() {
+                println!("{line}");
+            }
+            if !report.iter().any(|line| line.starts_with("[PROBLEM]")) {
+                println!("No problems found");
+            }
+
+            Ok(CommandResult::None)
+        }
+
+        Commands::MatchMap {
+            input,
+            output,
+            soft_match,
+            config_file,
+        } => {
+            let map = match_map(&input, soft_match, config_file.as_deref())?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, format!("{map}\n"))?;
+                    println!("Source matches written to {}", path.display());
+                }
+                None => println!("{map}"),
+            }
+
+            Ok(CommandResult::None)
+        }
+
+        Commands::Export {
+            input,
+            format,
+            output,
+        } => {
+            let list = export_library(&input, format)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, format!("{list}\n"))?;
+                    println!("Library written to {}", path.display());
+                }
+                None => println!("{list}"),
+            }
+
+            Ok(CommandResult::None)
+        }
+
+        Commands::Repair {
+            input,
+            output,
+            force,
+        } => {
+            let output = output.unwrap_or_else(|| {
+                let mut name = input.file_stem().unwrap_or_default().to_os_string();
+                name.push("_repaired.zip");
+                input.with_file_name(name)
+            });
+            if !force && output.try_exists()? {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "File with name {} already exists; use --force to replace it",
+                        output.display()
+                    ),
+                ));
+            }
+
+            let (repaired, count) =
+                repair_kotatsu(std::fs::File::open(&input)?, io::Cursor::new(Vec::new()))?;
+            std::fs::write(&output, repaired.into_inner())?;
+            println!("Repaired {count} manga, written to {}", output.display());
+
+            Ok(CommandResult::None)
+        }
+
+        Commands::ComputeId { source, url } => {
+            let manga_url = kotatsu::correct_url(&source, &url);
+            let manga_identifier = kotatsu::correct_identifier(&source, &manga_url);
+            println!("Manga URL: {manga_url}");
+            println!("Manga ID: {}", get_kotatsu_id(&source, &manga_identifier));
+            // Chapter URLs only go through the identifier correction
+            println!(
+                "Chapter ID: {}",
+                get_kotatsu_id(&source, &kotatsu::correct_identifier(&source, &url))
+            );
+
+            Ok(CommandResult::None)
+        }
+
+        Commands::SearchParsers { query } => {
+            let (parsers, _) = kotatsu::parse_parser_list(&std::fs::read_to_string(
+                DEFAULT_KOTATSU_PARSE_PATH.as_path(),
+            )?)?;
+            let query = query.to_lowercase();
+            let found = parsers
+                .iter()
+                .filter(|p| {
+                    p.name.to_lowercase().contains(&query)
+                        || p.title.to_lowercase().contains(&query)
+                        || p.domains.iter().any(|d| d.to_lowercase().contains(&query))
+                })
+                .collect::<Vec<_>>();
+            for parser in found.iter() {
+                println!(
+                    "{} ({}) [{}, {:?}]: {}",
+                    parser.name,
+                    parser.title,
+                    parser.locale.as_deref().unwrap_or("any"),
+                    parser.content_type,
+                    parser.domains.join(", ")
+                );
+            }
+            println!("{} of {} parsers matched", found.len(), parsers.len());
+
+            Ok(CommandResult::None)
         }
 
         Commands::Debug { input } => {
-            let backup = decode_neko_backup(std::fs::File::open(&input)?)?;
+            let backup = BackupReader::read_file(&input)?;
 
             println!("Manga:");
             for entry in backup.backup_manga.iter() {
@@ -496,18 +2456,44 @@ pub fn run_command(command: Commands) -> std::io::Result<CommandResult> {
             for entry in backup.backup_categories.iter() {
                 println!("{entry:?}")
             }
+            println!("Tracking:");
+            for manga in backup.backup_manga.iter() {
+                for entry in manga.tracking.iter() {
+                    println!(
+                        "{} (tracker {}): media {}, status {}, last read {}, {}",
+                        manga.title,
+                        entry.sync_id,
+                        entry.media_id,
+                        entry.status,
+                        entry.last_chapter_read,
+                        entry.tracking_url
+                    );
+                }
+            }
+            // Tachiyomi leaves `favorite` out when it is true, which decodes the same as false,
+            // so it can't be used to keep history-only manga out of Kotatsu's favourites
+            println!("Flags:");
+            for manga in backup.backup_manga.iter() {
+                let reading_mode = ReadingMode::from_viewer_flags(manga.viewer_flags);
+                println!(
+                    "{}: favorite {}, viewer flags {:#x} ({reading_mode:?}, Kotatsu {}), chapter flags {:#x}, flags {:#x}",
+                    manga.title,
+                    manga.favorite,
+                    manga.viewer_flags,
+                    reading_mode.kotatsu_mode().unwrap_or("automatic"),
+                    manga.chapter_flags,
+                    manga.flags
+                );
+            }
 
             Ok(CommandResult::None)
         }
 
         Commands::Clear | Commands::Delete => {
-            #[cfg(not(target_os = "windows"))]
-            let path = PROJECT_DIR.data_dir();
-            #[cfg(target_os = "windows")]
-            let path = PROJECT_DIR.data_dir().parent().ok_or(std::io::Error::new(
-                io::ErrorKind::Other,
-                "Unable to get Nekotatsu data folder path",
-            ))?;
+            let path = match DATA_DIR_OVERRIDE.as_deref() {
+                Some(path) => path,
+                None => default_data_root()?,
+            };
 
             if path.try_exists()? {
                 std::fs::remove_dir_all(path)?;
@@ -519,3 +2505,58 @@ pub fn run_command(command: Commands) -> std::io::Result<CommandResult> {
         }
     }
 }
+
+#[test]
+fn quote_csv_fields() {
+    assert_eq!(csv_field("Berserk"), "Berserk");
+    assert_eq!(csv_field("Miura, Kentarou"), "\"Miura, Kentarou\"");
+    assert_eq!(csv_field("The \"Hero\""), "\"The \"\"Hero\"\"\"");
+}
+
+#[test]
+fn output_name_keeps_dotted_input_names() {
+    assert_eq!(
+        output_name_for_input(
+            Path::new("backups/app.mihon_2024-05-01_12-00.tachibk"),
+            "zip"
+        ),
+        "app.mihon_2024-05-01_12-00.zip"
+    );
+    assert_eq!(
+        output_name_for_input(
+            Path::new("eu.kanade.tachiyomi_2024-05-01_12-00.proto.gz"),
+            "zip"
+        ),
+        "eu.kanade.tachiyomi_2024-05-01_12-00.zip"
+    );
+    assert_eq!(
+        output_name_for_input(Path::new("kotatsu.zip"), "tachibk"),
+        "kotatsu.tachibk"
+    );
+}
+
+#[test]
+fn numbered_output_path_keeps_dotted_stem() {
+    let dir = std::env::temp_dir().join(format!("nekotatsu-numbered-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.b.zip"), b"").unwrap();
+    std::fs::write(dir.join("a.b_1.zip"), b"").unwrap();
+    // Unrelated file sharing the part before the stem's dot
+    std::fs::write(dir.join("a.zip"), b"").unwrap();
+
+    assert_eq!(
+        numbered_output_path(&dir.join("a.b.zip")),
+        dir.join("a.b_2.zip")
+    );
+    assert_eq!(numbered_output_path(&dir.join("a")), dir.join("a_1"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn parse_since_timestamp() {
+    assert_eq!(parse_timestamp("1709251200000"), Ok(1709251200000));
+    assert_eq!(parse_timestamp("1970-01-01"), Ok(0));
+    assert_eq!(parse_timestamp("2024-03-01"), Ok(1709251200000));
+    assert!(parse_timestamp("2024-13-01").is_err());
+    assert!(parse_timestamp("yesterday").is_err());
+}
@@ -1,2 +1,2 @@
 pub mod command;
-use nekotatsu_core;
+pub use nekotatsu_core;
@@ -1,17 +1,19 @@
 use clap::Parser;
 use nekotatsu::command::{run_command, Args};
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
     let args = Args::parse();
 
     match args.command {
         Some(command) => {
-            run_command(command)?;
+            let result = run_command(command)?;
+            Ok(ExitCode::from(result.exit_code()))
         }
         None => {
             println!("Simple CLI tool that converts Neko backups into Kotatsu backups");
             println!("Run with -h for usage");
+            Ok(ExitCode::SUCCESS)
         }
     }
-    Ok(())
 }
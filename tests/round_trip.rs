@@ -0,0 +1,218 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use nekotatsu::command::{self, Commands};
+use nekotatsu::nekotatsu_core::{
+    backup::{BackupReader, BackupWriter},
+    nekotatsu::neko::Backup,
+    MangaConversionResult, MangaConverter,
+};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+const MANGADEX_ID: i64 = nekotatsu::nekotatsu_core::source_ids::MANGADEX;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(FIXTURES).join(name)
+}
+
+fn fixture_backup() -> Backup {
+    BackupReader::read_file(fixture("backup.tachibk")).expect("fixture is a valid backup")
+}
+
+fn convert(backup: Backup) -> MangaConversionResult {
+    MangaConverter::try_from_files(
+        File::open(fixture("kotatsu_parsers.json")).expect("fixture exists"),
+        File::open(fixture("tachi_sources.json")).expect("fixture exists"),
+    )
+    .expect("fixtures are valid")
+    .convert_backup(backup, "Library", &mut Vec::new(), &mut |_, _| true)
+    .expect("fixture backup converts")
+}
+
+/// Output directory of its own for each test, since tests run in parallel
+fn out_dir(test: &str) -> std::io::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("nekotatsu-{test}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Writes `result` as `kotatsu.zip` in `out_dir`
+fn write_kotatsu(result: &MangaConversionResult, out_dir: &Path) -> std::io::Result<PathBuf> {
+    let kotatsu_path = out_dir.join("kotatsu.zip");
+    BackupWriter::new(result).write_file(&kotatsu_path, &mut Vec::new())?;
+    Ok(kotatsu_path)
+}
+
+fn reverse_convert(input: &Path, output: &Path) -> std::io::Result<()> {
+    command::run_command(Commands::Convert {
+        input: input.display().to_string(),
+        more_inputs: Vec::new(),
+        output: Some(output.display().to_string()),
+        output_dir: None,
+        favorites_name: String::from("Library"),
+        verbose: false,
+        very_verbose: false,
+        quiet: false,
+        reverse: true,
+        input_format: None,
+        output_format: None,
+        compression: None,
+        soft_match: None,
+        lang: None,
+        force: true,
+        overwrite_policy: None,
+        config_file: None,
+        parsers: None,
+        sources: None,
+        whitelist: Vec::new(),
+        blacklist: Vec::new(),
+        config: None,
+        cancel_flag: None,
+        since: None,
+        category: Vec::new(),
+        exclude_category: Vec::new(),
+        rename_category: Vec::new(),
+        chapter_url_base: Vec::new(),
+        favorites_category: Vec::new(),
+        flatten_categories: false,
+        strip_read: false,
+        tags_from: None,
+        max_bookmarks: None,
+        fail_fast: false,
+        keep_going: true,
+        interactive: false,
+        include_unmatched: false,
+        keep_local: false,
+        unknown_state: None,
+        list_converted: false,
+        unmatched_out: None,
+        report_missing_parsers: None,
+        issue_template: None,
+        merge_into: None,
+        target_version: None,
+        print_output: false,
+    })?;
+    Ok(())
+}
+
+#[test]
+fn neko_to_kotatsu_to_neko() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = out_dir("round-trip")?;
+    let original = fixture_backup();
+    let result = convert(original.clone());
+    let kotatsu_path = write_kotatsu(&result, &out_dir)?;
+
+    let neko_path = out_dir.join("neko.tachibk");
+    reverse_convert(&kotatsu_path, &neko_path)?;
+    let round_tripped = BackupReader::read_file(&neko_path)?;
+
+    let converted_titles = result
+        .history
+        .iter()
+        .map(|entry| entry.manga.title.as_str())
+        .collect::<HashSet<_>>();
+    let round_tripped_titles = round_tripped
+        .backup_manga
+        .iter()
+        .map(|manga| manga.title.as_str())
+        .collect::<HashSet<_>>();
+    assert_eq!(converted_titles, round_tripped_titles);
+    assert!(original
+        .backup_manga
+        .iter()
+        .filter(|manga| converted_titles.contains(manga.title.as_str()))
+        .all(|manga| manga.source != 0));
+
+    for manga in round_tripped.backup_manga.iter() {
+        let before = original
+            .backup_manga
+            .iter()
+            .find(|m| m.title == manga.title)
+            .expect("title exists in original backup");
+        if before.source == MANGADEX_ID {
+            assert_eq!(manga.source, MANGADEX_ID);
+        }
+        // Artists are credited alongside the author since Kotatsu has no separate field
+        assert!(manga.author.starts_with(&before.author));
+        assert!(manga.author.contains(&before.artist));
+    }
+
+    let category_names =
+        |categories: &[nekotatsu::nekotatsu_core::nekotatsu::neko::BackupCategory]| {
+            categories
+                .iter()
+                .map(|c| c.name.clone())
+                .collect::<HashSet<_>>()
+        };
+    let mut expected_categories = category_names(&original.backup_categories);
+    expected_categories.insert(String::from("Library"));
+    assert_eq!(
+        category_names(&round_tripped.backup_categories),
+        expected_categories
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reverse_drops_orphaned_categories() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = out_dir("orphans")?;
+    let mut result = convert(fixture_backup());
+    // Favourite in a category that is not in the backup's categories
+    result.favourites[0].category_id = 99;
+    let kotatsu_path = write_kotatsu(&result, &out_dir)?;
+
+    let neko_path = out_dir.join("neko.tachibk");
+    reverse_convert(&kotatsu_path, &neko_path)?;
+    let reversed = BackupReader::read_file(&neko_path)?;
+
+    let orders = reversed
+        .backup_categories
+        .iter()
+        .map(|c| c.order)
+        .collect::<HashSet<_>>();
+    assert!(!orders.contains(&99));
+    assert!(reversed
+        .backup_manga
+        .iter()
+        .flat_map(|manga| manga.categories.iter())
+        .all(|order| orders.contains(order)));
+    assert_eq!(reversed.backup_manga.len(), result.history.len());
+
+    Ok(())
+}
+
+#[test]
+fn reverse_conversion_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = out_dir("deterministic")?;
+    let kotatsu_path = write_kotatsu(&convert(fixture_backup()), &out_dir)?;
+
+    let first = out_dir.join("first.tachibk");
+    let second = out_dir.join("second.tachibk");
+    reverse_convert(&kotatsu_path, &first)?;
+    reverse_convert(&kotatsu_path, &second)?;
+    assert_eq!(std::fs::read(&first)?, std::fs::read(&second)?);
+
+    let titles = BackupReader::read_file(&first)?
+        .backup_manga
+        .into_iter()
+        .map(|manga| manga.title)
+        .collect::<Vec<_>>();
+    let mut sorted = titles.clone();
+    sorted.sort();
+    assert_eq!(titles, sorted);
+
+    Ok(())
+}
+
+#[test]
+fn reverse_to_stdout_is_rejected() {
+    // Rejected before the input is read
+    let kotatsu_path = fixture("kotatsu.zip");
+    let error = reverse_convert(&kotatsu_path, Path::new("-")).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(!Path::new("-.tachibk").exists());
+}